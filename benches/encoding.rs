@@ -0,0 +1,39 @@
+//! Times encoding, decoding and the charset roundtrip check of every built-in game.
+//! Run with `cargo bench --features bench`.
+
+use sc3tools::benching::{self, BenchGame};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 200;
+
+/// Runs `f` `ITERATIONS` times, returning the mean time per run. The results are summed up and
+/// returned as well, so that the work can't be optimized out.
+fn time(mut f: impl FnMut() -> usize) -> (Duration, usize) {
+    let mut total = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        total += f();
+    }
+    (start.elapsed() / ITERATIONS, total)
+}
+
+fn main() {
+    for alias in benching::aliases() {
+        let game = BenchGame::new(alias).unwrap();
+        let text = game.sample_text(100);
+        let codes = game.encode(&text);
+
+        let (encode, a) = time(|| game.encode(&text).len());
+        let (decode, b) = time(|| game.decode(&codes).len());
+        let (roundtrip, c) = time(|| game.verify_roundtrip());
+        println!(
+            "{:<8} encode {:>10.2?}  decode {:>10.2?}  verify_roundtrip {:>10.2?}  ({} codes, checksum {})",
+            alias,
+            encode,
+            decode,
+            roundtrip,
+            codes.len(),
+            a + b + c
+        );
+    }
+}
@@ -0,0 +1,50 @@
+//! Entry points for the benchmarks in `benches/`, which cannot reach the private modules.
+
+use crate::gamedef::{self, GameDef};
+use crate::text::{self, Text};
+
+use std::borrow::Cow;
+
+/// A built-in game, constructed ahead of the timed code.
+pub struct BenchGame(&'static GameDef);
+
+impl BenchGame {
+    pub fn new(alias: &str) -> Option<Self> {
+        gamedef::get_by_alias(alias).map(BenchGame)
+    }
+
+    /// Text to encode, made of characters the game's charset has.
+    pub fn sample_text(&self, count: usize) -> String {
+        gamedef::generate_test_vectors(self.0, count)
+            .into_iter()
+            .map(|(input, _)| input)
+            .collect()
+    }
+
+    pub fn encode(&self, s: &str) -> Vec<u16> {
+        let text = Text(Cow::from(s));
+        text::encode_str(&text, self.0, self.0.prefers_fullwidth()).unwrap()
+    }
+
+    /// Decodes `codes`, taking unmapped Private Use Area glyphs as is, as the sample text
+    /// can contain them.
+    pub fn decode(&self, codes: &[u16]) -> String {
+        let options = text::DecodeOptions {
+            literal_unmapped_pua: true,
+            ..text::DecodeOptions::default()
+        };
+        let mut out = String::new();
+        text::decode_into_with(codes, self.0, false, &options, &mut out).unwrap();
+        out
+    }
+
+    /// Returns the number of glyphs that fail to roundtrip. See `GameDef::verify_roundtrip`.
+    pub fn verify_roundtrip(&self) -> usize {
+        self.0.verify_roundtrip().len()
+    }
+}
+
+/// Returns the primary alias of every built-in game.
+pub fn aliases() -> Vec<&'static str> {
+    gamedef::DEFS.iter().map(|def| &*def.aliases[0]).collect()
+}
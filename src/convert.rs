@@ -0,0 +1,246 @@
+use crate::format;
+use crate::gamedef::GameDef;
+use crate::sc3::{self, Sc3String, StringToken};
+use crate::text::{self, Char, EncodingError};
+use crate::warnings::{Warning, Warnings};
+
+use std::{borrow::Cow, collections::HashMap, error, fmt};
+
+/// What to do with characters that the target game cannot represent.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum UnmappedPolicy {
+    Abort,
+    Substitute,
+}
+
+/// Stands in for characters missing from the target charset under `UnmappedPolicy::Substitute`.
+pub const SUBSTITUTE_CHAR: char = '?';
+
+#[derive(Debug)]
+pub enum Error {
+    Sc3(sc3::Error),
+    TextEncoding(EncodingError),
+    Unmapped(Vec<String>),
+    Format(format::Error),
+    /// A glyph index that the remap table has no entry for.
+    Unremapped(u16),
+}
+
+impl error::Error for Error {}
+
+/// Re-encodes a string from the charset of one game to the charset of another.
+/// Under `UnmappedPolicy::Substitute`, each substitution is reported to `warnings`.
+pub fn convert_string(
+    s: &Sc3String,
+    from: &GameDef,
+    to: &GameDef,
+    policy: UnmappedPolicy,
+    warnings: &mut Warnings,
+) -> Result<Sc3String<'static>, Error> {
+    let substitute = text::encode_char(&Char::Regular(SUBSTITUTE_CHAR), to)?;
+    let mut unmapped = Vec::new();
+    let mut buf = Vec::new();
+    for tk in s.iter_with(from.string_framing()) {
+        let tk = match tk? {
+            StringToken::Text(codes) => {
+                let mut converted = Vec::with_capacity(codes.len());
+                for code in codes.iter() {
+                    let ch = text::decode_char(*code, from)?;
+                    match text::encode_char(&ch, to) {
+                        Ok(code) => converted.push(code),
+                        Err(EncodingError::CharNotInCharset(_)) => {
+                            unmapped.push(match ch {
+                                Char::Regular(c) => c.to_string(),
+                                Char::Compound(s) => format!("[{}]", s),
+                            });
+                            converted.push(substitute);
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                StringToken::Text(converted.into())
+            }
+            tk => tk,
+        };
+        tk.encode_with(&mut buf, to.string_framing())?;
+    }
+    StringToken::Terminator.encode_with(&mut buf, to.string_framing())?;
+
+    if policy == UnmappedPolicy::Abort && !unmapped.is_empty() {
+        return Err(Error::Unmapped(unmapped));
+    }
+
+    for ch in unmapped {
+        warnings.push(Warning::Substituted(ch));
+    }
+    Ok(Sc3String(buf.into()))
+}
+
+/// Rewrites the character codes of every string in a script according to `remap`, a map from
+/// old glyph indices to new ones such as the one returned by `GameDef::compact_charset`.
+/// The strings keep their length, so the string index is left untouched.
+pub fn remap_script(
+    script: &[u8],
+    remap: &HashMap<u16, u16>,
+    gamedef: &GameDef,
+) -> Result<Vec<u8>, Error> {
+    let index = match gamedef.script_layout() {
+        Some(layout) => layout.read_string_index(script)?,
+        None => format::read_string_index(script)?,
+    };
+    let framing = gamedef.string_framing();
+    let mut remapped = script.to_vec();
+    for handle in index.iter() {
+        let s = Sc3String(Cow::from(handle.slice(script)?));
+        let mut buf = Vec::with_capacity(handle.size());
+        for tk in s.iter_with(framing) {
+            let tk = match tk? {
+                StringToken::Text(codes) => {
+                    let codes = codes
+                        .iter()
+                        .map(|code| {
+                            let index = gamedef
+                                .glyph_index(*code)
+                                .ok_or(EncodingError::IllegalCharCode(*code))?;
+                            match remap.get(&index) {
+                                Some(index) => Ok(gamedef.char_code(*index)),
+                                None => Err(Error::Unremapped(index)),
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    StringToken::Text(codes.into())
+                }
+                tk => tk,
+            };
+            tk.encode_with(&mut buf, framing)?;
+        }
+        StringToken::Terminator.encode_with(&mut buf, framing)?;
+        remapped[handle.0.start as usize..][..buf.len()].copy_from_slice(&buf);
+    }
+
+    Ok(remapped)
+}
+
+impl From<format::Error> for Error {
+    fn from(err: format::Error) -> Self {
+        Error::Format(err)
+    }
+}
+
+impl From<sc3::Error> for Error {
+    fn from(err: sc3::Error) -> Self {
+        Error::Sc3(err)
+    }
+}
+
+impl From<EncodingError> for Error {
+    fn from(err: EncodingError) -> Self {
+        Error::TextEncoding(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Sc3(err.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Sc3(err) => fmt::Display::fmt(err, f),
+            Error::TextEncoding(err) => fmt::Display::fmt(err, f),
+            Error::Unmapped(chars) => write!(
+                f,
+                "the target game cannot represent the following characters: {}",
+                chars.join(" ")
+            ),
+            Error::Format(err) => fmt::Display::fmt(err, f),
+            Error::Unremapped(index) => {
+                write!(f, "glyph index {} is missing from the remap table", index)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coz::CozString;
+    use crate::fixtures::load_fixture;
+    use crate::{format, gamedef};
+    use std::borrow::Cow;
+
+    #[test]
+    fn convert_fixture() {
+        let (from, to) = (
+            gamedef::get_by_alias("sghd").unwrap(),
+            gamedef::get_by_alias("sg0").unwrap(),
+        );
+        let script = load_fixture("sghd", "sample.scx");
+        let index = format::read_string_index(&script).unwrap();
+        for (i, handle) in index.iter().enumerate() {
+            let s = Sc3String(Cow::from(handle.slice(&script).unwrap()));
+            let mut warnings = Warnings::new();
+            let converted =
+                convert_string(&s, from, to, UnmappedPolicy::Abort, &mut warnings).unwrap();
+            assert!(warnings.is_empty());
+            assert_eq!(
+                converted.serialize(to, true).unwrap(),
+                s.serialize(from, true).unwrap(),
+                "string {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn convert_unmapped() {
+        let (from, to) = (
+            gamedef::get_by_alias("sghd").unwrap(),
+            gamedef::get_by_alias("sg0").unwrap(),
+        );
+        let line = CozString(Cow::from("a¬b"));
+        let s = Sc3String::deserialize(&line, from, false).unwrap();
+
+        let mut warnings = Warnings::new();
+        let res = convert_string(&s, from, to, UnmappedPolicy::Abort, &mut warnings);
+        assert!(matches!(res, Err(Error::Unmapped(chars)) if chars == vec!["¬"]));
+        assert!(warnings.is_empty());
+
+        let converted =
+            convert_string(&s, from, to, UnmappedPolicy::Substitute, &mut warnings).unwrap();
+        assert_eq!(
+            warnings.iter().collect::<Vec<_>>(),
+            vec![&Warning::Substituted("¬".to_string())]
+        );
+        assert_eq!(converted.serialize(to, false).unwrap().0, "a?b");
+    }
+
+    #[test]
+    fn remap_to_compacted_charset() {
+        let def = gamedef::test_def(" Tu t\nr!", "");
+        let (charset, remap) = def.compact_charset();
+        let compacted = gamedef::test_def(&gamedef::build_charset(&charset), "");
+
+        let line = CozString(Cow::from("Tuturu![linebreak]Tu"));
+        let s = Sc3String::deserialize(&line, &def, false).unwrap().0;
+        // A single string right after the index
+        let mut script = b"SC3\0".to_vec();
+        for offset in &[12u32, 16, 16] {
+            script.extend_from_slice(&offset.to_le_bytes());
+        }
+        script.extend_from_slice(&s);
+
+        let remapped = remap_script(&script, &remap, &def).unwrap();
+        assert_eq!(remapped.len(), script.len());
+        assert_ne!(remapped, script);
+        let s = Sc3String(Cow::from(&remapped[16..]));
+        assert_eq!(s.serialize(&compacted, false).unwrap(), line);
+
+        assert!(matches!(
+            remap_script(&script, &HashMap::new(), &def),
+            Err(Error::Unremapped(1))
+        ));
+    }
+}
@@ -21,6 +21,8 @@ pub enum Error {
     Parsing(ParseError),
     TextEncoding(text::EncodingError),
     Serialization(sc3::Error),
+    /// The line encodes to more character codes than a string record can hold (count, limit).
+    RecordTooLong(usize, usize),
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -82,7 +84,7 @@ impl<'a> Sc3String<'_> {
         keep_fullwidth_chars: bool,
     ) -> Result<CozString, Error> {
         let mut buf = String::new();
-        for tk in self.iter() {
+        for tk in self.iter_with(gamedef.string_framing()) {
             let seg = tk?.serialize(&gamedef, keep_fullwidth_chars)?;
             write!(buf, "{}", seg).unwrap();
         }
@@ -95,17 +97,140 @@ impl<'a> Sc3String<'_> {
         gamedef: &GameDef,
         convert_to_fullwidth: bool,
     ) -> Result<Sc3String<'a>, Error> {
+        let framing = gamedef.string_framing();
         let mut buf = Cursor::new(Vec::new());
         for seg in s.iter() {
             let tk = StringToken::deserialize(&seg, &gamedef, convert_to_fullwidth)?;
-            tk.encode(&mut buf).unwrap();
+            tk.encode_with(&mut buf, framing).unwrap();
         }
 
-        StringToken::Terminator.encode(&mut buf).unwrap();
+        StringToken::Terminator
+            .encode_with(&mut buf, framing)
+            .unwrap();
         Ok(Sc3String(buf.into_inner().into()))
     }
 }
 
+/// Serializes decoded tokens into markup that `Sc3String::deserialize` accepts.
+/// Fullwidth characters are kept, so that the markup encodes back to the same character codes.
+pub fn tokens_to_markup(
+    tokens: &[StringToken],
+    gamedef: &GameDef,
+) -> Result<String, text::EncodingError> {
+    let mut buf = String::new();
+    for tk in tokens {
+        let seg = tk.clone().serialize(gamedef, true)?;
+        write!(buf, "{}", seg).unwrap();
+    }
+
+    Ok(buf)
+}
+
+/// Encodes markup as it is typed, one character at a time.
+///
+/// Text is encoded as soon as it arrives, while bracketed sequences are held back until they are
+/// closed, as they may turn out to be a tag or a compound character. The tokens of the string are
+/// collected along the way, and `finish` hands them over to be written out with
+/// `StringToken::encode_with` and the game's `GameDef::string_framing`. They encode to what
+/// `Sc3String::deserialize` produces for the whole markup.
+pub struct IncrementalEncoder<'g> {
+    gamedef: &'g GameDef,
+    convert_to_fullwidth: bool,
+    pending: String,
+    tokens: Vec<StringToken<'static>>,
+}
+
+impl<'g> IncrementalEncoder<'g> {
+    pub fn new(gamedef: &'g GameDef, convert_to_fullwidth: bool) -> Self {
+        Self {
+            gamedef,
+            convert_to_fullwidth,
+            pending: String::new(),
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Feeds a character, returning the character codes it completes, if any. A completed tag
+    /// yields no character codes.
+    pub fn push(&mut self, ch: char) -> Result<Option<Vec<u16>>, Error> {
+        let mut codes = Vec::new();
+        if ch == '[' && !self.pending.is_empty() {
+            // The previous bracket was never closed, so it was plain text after all.
+            codes.extend(self.flush()?);
+        }
+        self.pending.push(ch);
+        if self.pending.starts_with('[') && ch != ']' {
+            return Ok(if codes.is_empty() { None } else { Some(codes) });
+        }
+
+        codes.extend(self.flush()?);
+        Ok(Some(codes))
+    }
+
+    /// Flushes whatever is still pending and returns the tokens of the string, terminator
+    /// included.
+    pub fn finish(mut self) -> Result<Vec<StringToken<'static>>, Error> {
+        self.flush()?;
+        self.tokens.push(StringToken::Terminator);
+        Ok(self.tokens)
+    }
+
+    /// Encodes the pending markup, returning the character codes it encodes to.
+    fn flush(&mut self) -> Result<Vec<u16>, Error> {
+        let pending = std::mem::take(&mut self.pending);
+        let seg = match StringSegment::tag(&pending) {
+            Ok(("", tag)) => StringSegment::Tag(tag),
+            _ => StringSegment::Text(text::Text(Cow::from(pending.as_str()))),
+        };
+        let tk = StringToken::deserialize(&seg, self.gamedef, self.convert_to_fullwidth)?;
+        let codes = match &tk {
+            StringToken::Text(codes) => codes.to_vec(),
+            _ => Vec::new(),
+        };
+        match (self.tokens.last_mut(), tk) {
+            // Keep text typed piecemeal in a single token
+            (Some(StringToken::Text(prev)), StringToken::Text(codes)) => {
+                prev.to_mut().extend_from_slice(&codes)
+            }
+            (_, tk) => self.tokens.push(tk.into_owned()),
+        }
+        Ok(codes)
+    }
+}
+
+/// Checks that every replacement line can be encoded, without producing any output.
+/// Lines encoding to more character codes than the game's `max_record_codepoints` fail too.
+/// Returns the line index and error of each line that failed.
+pub fn validate_replacements(
+    replacements: &[(usize, String)],
+    gamedef: &GameDef,
+) -> Vec<(usize, Error)> {
+    replacements
+        .iter()
+        .filter_map(|(i, s)| {
+            let s = CozString(Cow::from(s.as_str()));
+            let mut codepoints = 0;
+            s.iter()
+                .try_for_each(|seg| {
+                    if let StringToken::Text(codes) =
+                        StringToken::deserialize(&seg, gamedef, false)?
+                    {
+                        codepoints += codes.len();
+                    }
+                    Ok(())
+                })
+                .and_then(|_| match gamedef.max_record_codepoints() {
+                    Some(limit) if codepoints > limit => {
+                        Err(Error::RecordTooLong(codepoints, limit))
+                    }
+                    _ => Ok(()),
+                })
+                .err()
+                .map(|err| (*i, err))
+        })
+        .collect()
+}
+
 impl<'a> CozString<'a> {
     pub fn iter(&self) -> CozStringIter {
         CozStringIter { remaining: &self.0 }
@@ -375,6 +500,11 @@ impl fmt::Display for Error {
             Error::Parsing(err) => fmt::Display::fmt(&err, f),
             Error::TextEncoding(err) => fmt::Display::fmt(&err, f),
             Error::Serialization(err) => fmt::Display::fmt(&err, f),
+            Error::RecordTooLong(count, limit) => write!(
+                f,
+                "line encodes to {} characters, but a record holds at most {}",
+                count, limit
+            ),
         }
     }
 }
@@ -501,4 +631,77 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn validate_replacements_reports_unencodable_lines() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let replacements = vec![
+            (0, "[name]LuLu[line]Hi I am LuLu".to_string()),
+            (3, "Tuturu\u{1F600}".to_string()),
+            (7, "El Psy Kongroo".to_string()),
+        ];
+        let failures = validate_replacements(&replacements, gamedef);
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(
+            &failures[0],
+            (3, Error::TextEncoding(text::EncodingError::CharNotInCharset(ch))) if ch == "\u{1F600}"
+        ));
+    }
+
+    #[test]
+    fn validate_replacements_reports_overlong_records() {
+        let gamedef = gamedef::test_def(" LuTtr!", "").with_max_record_codepoints(10);
+        // Names count towards the limit, markup doesn't
+        let replacements = vec![
+            (0, "[name]LuLu[line]Tuturu".to_string()),
+            (1, "[name]LuLu[line]Tuturu!".to_string()),
+        ];
+        let failures = validate_replacements(&replacements, &gamedef);
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(&failures[0], (1, Error::RecordTooLong(11, 10))));
+    }
+
+    #[test]
+    fn incremental_ruby_markup() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let markup = "[ruby-base]Kyouma[ruby-text-start]Okabe[ruby-text-end] [ü][x";
+        let mut encoder = IncrementalEncoder::new(gamedef, false);
+        let mut codes = Vec::new();
+        for ch in markup.chars() {
+            let res = encoder.push(ch).unwrap();
+            // Nothing comes out while a bracket is open
+            if ch == '[' || ch == 'x' {
+                assert_eq!(res, None);
+            }
+            codes.extend(res.unwrap_or_default());
+        }
+        let mut encoded = Vec::new();
+        for tk in encoder.finish().unwrap() {
+            tk.encode_with(&mut encoded, gamedef.string_framing())
+                .unwrap();
+        }
+
+        let s = CozString(Cow::from(markup));
+        let expected = Sc3String::deserialize(&s, gamedef, false).unwrap();
+        assert_eq!(encoded, expected.0.into_owned());
+        let text = text::encode_str(&text::Text("KyoumaOkabe [ü]".into()), gamedef, false);
+        assert_eq!(codes, text.unwrap());
+    }
+
+    #[test]
+    fn markup_roundtrip() {
+        for (alias, name) in &[("sg0", "sample.scx"), ("rnd", "sample.msb")] {
+            let gamedef = gamedef::get_by_alias(alias).unwrap();
+            let script = crate::fixtures::load_fixture(alias, name);
+            let index = crate::format::read_string_index(&script).unwrap();
+            for handle in index.iter() {
+                let data = handle.slice(&script).unwrap();
+                let s = Sc3String(Cow::from(data));
+                let tokens = s.iter().collect::<Result<Vec<_>, _>>().unwrap();
+                let markup = CozString(Cow::from(tokens_to_markup(&tokens, gamedef).unwrap()));
+                let encoded = Sc3String::deserialize(&markup, gamedef, false).unwrap();
+                assert_eq!(&encoded.0[..], data, "{}", markup);
+            }
+        }
+    }
 }
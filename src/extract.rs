@@ -0,0 +1,780 @@
+use crate::coz::{self, StringSegment};
+use crate::format;
+use crate::gamedef::{self, GameDef};
+use crate::sc3::{ControlCode, Sc3String, StringToken};
+use crate::text;
+
+use std::fmt::Write;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    error, fmt,
+    ops::Range,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Format(format::Error),
+    String(usize, coz::Error),
+    StringCountMismatch(usize, usize),
+}
+
+impl error::Error for Error {}
+
+/// A byte range of ruby base text, paired with its reading.
+pub type RubyAnnotation = (Range<usize>, String);
+
+/// The outcome of extracting a single string, which on failure holds the offset of the string.
+pub type StringResult = Result<ExtractedString, (u32, Error)>;
+
+/// A string extracted from a script, with ruby annotations split out of the text.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExtractedString {
+    pub index: usize,
+    pub offset: u32,
+    /// The string serialized as markup, minus the ruby readings and the tags delimiting them.
+    pub text: String,
+    /// Ruby annotations, with base text ranges pointing into `text`.
+    pub ruby: Vec<RubyAnnotation>,
+}
+
+impl ExtractedString {
+    /// Puts the ruby annotations back into the text, giving the markup of the whole string.
+    pub fn to_markup(&self) -> String {
+        let mut markup = String::with_capacity(self.text.len());
+        let mut pos = 0;
+        for (base, reading) in &self.ruby {
+            markup.push_str(&self.text[pos..base.start]);
+            if !base.is_empty() {
+                markup.push_str("[ruby-base]");
+                markup.push_str(&self.text[base.clone()]);
+            }
+            write!(markup, "[ruby-text-start]{}[ruby-text-end]", reading).unwrap();
+            pos = base.end;
+        }
+        markup.push_str(&self.text[pos..]);
+        markup
+    }
+}
+
+pub fn extract_strings(script: &[u8], gamedef: &GameDef) -> Result<Vec<ExtractedString>, Error> {
+    extract_strings_limited(script, gamedef, usize::MAX)
+}
+
+/// Like `extract_strings`, but stops after the first `max` strings, leaving the rest undecoded.
+/// Meant for previewing large scripts.
+pub fn extract_strings_limited(
+    script: &[u8],
+    gamedef: &GameDef,
+    max: usize,
+) -> Result<Vec<ExtractedString>, Error> {
+    let index = string_index(script, gamedef)?;
+    index
+        .iter()
+        .take(max)
+        .enumerate()
+        .map(|(i, handle)| extract_indexed(script, i, &handle, gamedef))
+        .collect()
+}
+
+/// Like `extract_strings`, but keeps going past strings that fail to decode, reporting each
+/// failure along with the offset of the string. Only an unreadable string index fails outright.
+pub fn extract_results(script: &[u8], gamedef: &GameDef) -> Result<Vec<StringResult>, Error> {
+    let index = string_index(script, gamedef)?;
+    Ok(index
+        .iter()
+        .enumerate()
+        .map(|(i, handle)| {
+            extract_indexed(script, i, &handle, gamedef).map_err(|err| (handle.0.start, err))
+        })
+        .collect())
+}
+
+fn extract_indexed(
+    script: &[u8],
+    i: usize,
+    handle: &format::StringHandle,
+    gamedef: &GameDef,
+) -> Result<ExtractedString, Error> {
+    let data = handle.slice(script)?;
+    let s = Sc3String(Cow::from(data));
+    extract_string(&s, gamedef)
+        .map(|(text, ruby)| ExtractedString {
+            index: i,
+            offset: handle.0.start,
+            text,
+            ruby,
+        })
+        .map_err(|err| Error::String(i, err))
+}
+
+/// Lists, in table order, the offsets of a translation table (as read by `po::import_po`)
+/// at which no string of the script starts, as happens when the table was made for
+/// an older version of the script.
+pub fn orphan_offsets(
+    table: &[(u32, String)],
+    script: &[u8],
+    gamedef: &GameDef,
+) -> Result<Vec<u32>, Error> {
+    let index = string_index(script, gamedef)?;
+    let starts: BTreeSet<_> = index.iter().map(|handle| handle.0.start).collect();
+    Ok(table
+        .iter()
+        .map(|(offset, _)| *offset)
+        .filter(|offset| !starts.contains(offset))
+        .collect())
+}
+
+/// How far the translation of a script has come.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TranslationProgress {
+    pub total: usize,
+    /// Strings that differ from the source.
+    pub changed: usize,
+    pub untranslated: usize,
+}
+
+impl TranslationProgress {
+    pub fn percentage(&self) -> f64 {
+        if self.total == 0 {
+            100.0
+        } else {
+            self.changed as f64 * 100.0 / self.total as f64
+        }
+    }
+}
+
+/// Counts the strings of a translated script that differ from the source script,
+/// taking those to be translated.
+pub fn translation_progress(
+    source: &[u8],
+    translated: &[u8],
+    gamedef: &GameDef,
+) -> Result<TranslationProgress, Error> {
+    let source = extract_strings(source, gamedef)?;
+    let translated = extract_strings(translated, gamedef)?;
+    if source.len() != translated.len() {
+        return Err(Error::StringCountMismatch(source.len(), translated.len()));
+    }
+
+    let changed = source
+        .iter()
+        .zip(&translated)
+        .filter(|(a, b)| a.text != b.text || a.ruby != b.ruby)
+        .count();
+    Ok(TranslationProgress {
+        total: source.len(),
+        changed,
+        untranslated: source.len() - changed,
+    })
+}
+
+/// Collects every character code referenced by the text of the given scripts,
+/// including the codes of compound characters and of PUA glyphs lacking a mapping.
+pub fn used_codepoints(scripts: &[&[u8]], gamedef: &GameDef) -> Result<BTreeSet<u16>, Error> {
+    let options = text::DecodeOptions {
+        literal_unmapped_pua: true,
+        ..text::DecodeOptions::default()
+    };
+    let mut used = BTreeSet::new();
+    for script in scripts {
+        let index = string_index(script, gamedef)?;
+        for (i, handle) in index.iter().enumerate() {
+            let s = Sc3String(Cow::from(handle.slice(script)?));
+            for tk in s.iter_with(gamedef.string_framing()) {
+                let tk = tk.map_err(|err| Error::String(i, err.into()))?;
+                if let StringToken::Text(codes) = tk {
+                    for code in codes.iter() {
+                        text::decode_char_with(*code, gamedef, &options)
+                            .map_err(|err| Error::String(i, err.into()))?;
+                        used.insert(*code);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(used)
+}
+
+/// Counts the occurrences of each control code across the strings of a script.
+/// String terminators are not counted.
+pub fn control_code_census(
+    script: &[u8],
+    gamedef: &GameDef,
+) -> Result<BTreeMap<ControlCode, usize>, Error> {
+    let mut census = BTreeMap::new();
+    let index = string_index(script, gamedef)?;
+    for (i, handle) in index.iter().enumerate() {
+        let s = Sc3String(Cow::from(handle.slice(script)?));
+        for tk in s.iter_with(gamedef.string_framing()) {
+            let tk = tk.map_err(|err| Error::String(i, err.into()))?;
+            if let Some(code) = tk.control_code() {
+                *census.entry(code).or_insert(0) += 1;
+            }
+        }
+    }
+
+    Ok(census)
+}
+
+/// Collects the glyphs of the charset that no script references, which a shipping font can drop.
+/// A compound character in use keeps every glyph standing for the same expansion, as the encoder
+/// is free to pick any of them.
+pub fn unused_glyphs(scripts: &[&[u8]], gamedef: &GameDef) -> Result<BTreeSet<char>, Error> {
+    let charset = gamedef.charset();
+    let used: BTreeSet<_> = used_codepoints(scripts, gamedef)?
+        .into_iter()
+        .filter_map(|code| gamedef.glyph_of(code))
+        .collect();
+    let used_expansions: BTreeSet<_> = used
+        .iter()
+        .filter_map(|ch| gamedef.compound_chars.get(ch))
+        .collect();
+    let unused = charset
+        .iter()
+        .filter(|ch| **ch != '\0' && !used.contains(ch))
+        .filter(|ch| {
+            gamedef
+                .compound_chars
+                .get(ch)
+                .map_or(true, |expansion| !used_expansions.contains(expansion))
+        })
+        .cloned()
+        .collect();
+    Ok(unused)
+}
+
+/// Ranks the built-in games by how well they fit a script, best first, each with a confidence
+/// between 0 and 1: the share of the script's character codes that stand for a glyph of the
+/// game's charset. Games whose script layout cannot read the script are left out.
+pub fn detect_game(script: &[u8]) -> Vec<(&'static GameDef, f64)> {
+    fn score(script: &[u8], gamedef: &GameDef) -> Option<f64> {
+        let index = string_index(script, gamedef).ok()?;
+        let (mut total, mut glyphs) = (0usize, 0usize);
+        for handle in index.iter() {
+            let s = Sc3String(Cow::from(handle.slice(script).ok()?));
+            for tk in s.iter_with(gamedef.string_framing()) {
+                if let StringToken::Text(codes) = tk.ok()? {
+                    total += codes.len();
+                    glyphs += codes
+                        .iter()
+                        .filter(|code| !gamedef.is_padding(**code))
+                        .filter(|code| {
+                            gamedef
+                                .glyph_of(**code)
+                                .map_or(false, |ch| !ch.is_control())
+                        })
+                        .count();
+                }
+            }
+        }
+        Some(if total == 0 {
+            0.0
+        } else {
+            glyphs as f64 / total as f64
+        })
+    }
+
+    let mut candidates: Vec<_> = gamedef::DEFS
+        .iter()
+        .filter_map(|def| score(script, def).map(|score| (def, score)))
+        .collect();
+    candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    candidates
+}
+
+/// Lists the character codes used by a script that stand for a character of the reserved range
+/// without a compound mapping. These hint at a script made for a different revision of the charset.
+pub fn reserved_collisions(script: &[u8], gamedef: &GameDef) -> Result<Vec<u16>, Error> {
+    let reserved = match gamedef.reserved_codepoints() {
+        Some(reserved) => reserved,
+        None => return Ok(Vec::new()),
+    };
+    let collisions = used_codepoints(&[script], gamedef)?
+        .into_iter()
+        .filter(|code| {
+            gamedef.glyph_of(*code).map_or(false, |ch| {
+                reserved.contains(&ch) && !gamedef.compound_chars.contains_key(&ch)
+            })
+        })
+        .collect();
+    Ok(collisions)
+}
+
+/// Finds the strings of a script whose text contains `needle`, returning their indices.
+/// Unless `across_controls` is set, a match cannot span a control code such as a line break.
+pub fn find_text(
+    script: &[u8],
+    gamedef: &GameDef,
+    needle: &str,
+    across_controls: bool,
+) -> Result<Vec<usize>, Error> {
+    let index = string_index(script, gamedef)?;
+    let mut found = Vec::new();
+    for (i, handle) in index.iter().enumerate() {
+        let s = Sc3String(Cow::from(handle.slice(script)?));
+        let mut runs = vec![String::new()];
+        for tk in s.iter_with(gamedef.string_framing()) {
+            match tk.map_err(|err| Error::String(i, err.into()))? {
+                StringToken::Text(codes) => {
+                    let run = runs.last_mut().unwrap();
+                    text::decode_into(&codes, gamedef, false, run)
+                        .map_err(|err| Error::String(i, err.into()))?;
+                }
+                _ if !across_controls => runs.push(String::new()),
+                _ => {}
+            }
+        }
+        if runs.iter().any(|run| run.contains(needle)) {
+            found.push(i);
+        }
+    }
+
+    Ok(found)
+}
+
+/// Renders a script as a listing for review. Each string gets a header line with its index,
+/// offset and annotated text, followed by a line per token with its offset and opcode.
+pub fn dump_script(script: &[u8], gamedef: &GameDef) -> Result<String, Error> {
+    let index = string_index(script, gamedef)?;
+    let mut out = String::new();
+    for (i, handle) in index.iter().enumerate() {
+        let data = handle.slice(script)?;
+        let annotated = annotate_string(&Sc3String(Cow::from(data)), gamedef)
+            .map_err(|err| Error::String(i, err))?;
+        writeln!(out, "#{} @{:08X}: {}", i, handle.0.start, annotated).unwrap();
+
+        let mut remaining = data;
+        while !remaining.is_empty() {
+            let offset = handle.0.start as usize + data.len() - remaining.len();
+            let (rem, tk) = StringToken::decode_with(remaining, gamedef.string_framing())
+                .map_err(|err| Error::String(i, err.into()))?;
+            let opcode = match tk {
+                StringToken::Text(_) => "..".to_string(),
+                _ => format!("{:02X}", remaining[0]),
+            };
+            let description = match tk {
+                StringToken::Terminator => "end".to_string(),
+                tk => match tk.serialize(gamedef, false) {
+                    Ok(StringSegment::Text(text)) => format!("text {}", text.as_str()),
+                    Ok(tag) => strip_brackets(&tag.to_string()).to_string(),
+                    Err(err) => return Err(Error::String(i, err.into())),
+                },
+            };
+            writeln!(out, "  {:08X}  {}  {}", offset, opcode, description).unwrap();
+            remaining = rem;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Renders a string with its control codes as named tags: `<br>` for line breaks,
+/// `<name:...>` for speaker names and `<ruby base=... reading=...>` for ruby text.
+fn annotate_string(s: &Sc3String, gamedef: &GameDef) -> Result<String, coz::Error> {
+    let mut out = String::new();
+    let mut name: Option<String> = None;
+    let mut base: Option<String> = None;
+    let mut reading: Option<String> = None;
+    for tk in s.iter_with(gamedef.string_framing()) {
+        match tk? {
+            StringToken::NameStart => name = Some(String::new()),
+            StringToken::LineStart => {
+                if let Some(name) = name.take() {
+                    write!(out, "<name:{}>", name).unwrap();
+                }
+            }
+            StringToken::RubyBaseStart => base = Some(String::new()),
+            StringToken::RubyTextStart => reading = Some(String::new()),
+            StringToken::RubyTextEnd => write!(
+                out,
+                "<ruby base={} reading={}>",
+                base.take().unwrap_or_default(),
+                reading.take().unwrap_or_default()
+            )
+            .unwrap(),
+            StringToken::LineBreak => out.push_str("<br>"),
+            tk => {
+                let seg = match tk.serialize(gamedef, false)? {
+                    StringSegment::Text(text) => text.as_str().to_string(),
+                    tag => format!("<{}>", strip_brackets(&tag.to_string())),
+                };
+                let buf = reading
+                    .as_mut()
+                    .or(base.as_mut())
+                    .or(name.as_mut())
+                    .unwrap_or(&mut out);
+                buf.push_str(&seg);
+            }
+        }
+    }
+    if let Some(name) = name {
+        write!(out, "<name:{}>", name).unwrap();
+    }
+
+    Ok(out)
+}
+
+fn strip_brackets(tag: &str) -> &str {
+    tag.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Reads the string index of a script, making sure it has the layout the game uses.
+fn string_index(script: &[u8], gamedef: &GameDef) -> Result<format::StringIndex, Error> {
+    Ok(match gamedef.script_layout() {
+        Some(layout) => layout.read_string_index(script)?,
+        None => format::read_string_index(script)?,
+    })
+}
+
+fn extract_string(
+    s: &Sc3String,
+    gamedef: &GameDef,
+) -> Result<(String, Vec<RubyAnnotation>), coz::Error> {
+    let mut text = String::new();
+    let mut ruby = Vec::new();
+    let mut base_start = None;
+    let mut reading: Option<RubyAnnotation> = None;
+
+    for tk in s.iter_with(gamedef.string_framing()) {
+        match tk? {
+            StringToken::RubyBaseStart => base_start = Some(text.len()),
+            StringToken::RubyTextStart => {
+                let start = base_start.take().unwrap_or(text.len());
+                reading = Some((start..text.len(), String::new()));
+            }
+            StringToken::RubyTextEnd => ruby.extend(reading.take()),
+            tk => {
+                let seg = tk.serialize(gamedef, false)?;
+                let buf = match reading.as_mut() {
+                    Some((_, reading)) => reading,
+                    None => &mut text,
+                };
+                write!(buf, "{}", seg).unwrap();
+            }
+        }
+    }
+
+    Ok((text, ruby))
+}
+
+impl From<format::Error> for Error {
+    fn from(err: format::Error) -> Self {
+        Error::Format(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Format(err) => fmt::Display::fmt(err, f),
+            Error::String(i, err) => write!(f, "string {}: {}", i, err),
+            Error::StringCountMismatch(source, translated) => write!(
+                f,
+                "the source has {} strings, but the translation has {}",
+                source, translated
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coz::CozString;
+    use crate::fixtures::load_fixture;
+    use crate::gamedef;
+
+    fn build_scx(strings: &[&str], gamedef: &GameDef) -> Vec<u8> {
+        let strings: Vec<_> = strings
+            .iter()
+            .map(|s| {
+                let s = CozString(Cow::from(*s));
+                Sc3String::deserialize(&s, gamedef, false)
+                    .unwrap()
+                    .0
+                    .into_owned()
+            })
+            .collect();
+        let index_start = 12u32;
+        let heap_start = index_start + 4 * strings.len() as u32;
+        let mut data = b"SC3\0".to_vec();
+        data.extend_from_slice(&index_start.to_le_bytes());
+        data.extend_from_slice(&heap_start.to_le_bytes());
+        let mut offset = heap_start;
+        for s in &strings {
+            data.extend_from_slice(&offset.to_le_bytes());
+            offset += s.len() as u32;
+        }
+        for s in &strings {
+            data.extend_from_slice(s);
+        }
+        data
+    }
+
+    #[test]
+    fn extract_ruby() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let script = build_scx(
+            &[
+                "[name]Okabe[line]Hello",
+                "I am [ruby-base]Hououin[ruby-text-start]Okabe[ruby-text-end] Kyouma",
+            ],
+            gamedef,
+        );
+        let strings = extract_strings(&script, gamedef).unwrap();
+        assert_eq!(strings.len(), 2);
+        assert_eq!(strings[0].text, "[name]Okabe[line]Hello");
+        assert!(strings[0].ruby.is_empty());
+
+        let s = &strings[1];
+        assert_eq!(s.text, "I am Hououin Kyouma");
+        assert_eq!(s.ruby, vec![(5..12, "Okabe".to_string())]);
+        assert_eq!(&s.text[s.ruby[0].0.clone()], "Hououin");
+    }
+
+    #[test]
+    fn extract_first_strings() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let mut script = build_scx(&["El Psy Kongroo", "Okarin", "Tuturu"], gamedef);
+        let limited = extract_strings_limited(&script, gamedef, 2).unwrap();
+        assert_eq!(limited, extract_strings(&script, gamedef).unwrap()[..2]);
+
+        // An unrecognized instruction at the start of the last string goes unnoticed
+        let len = script.len();
+        script[len - 13] = 0x07;
+        assert!(extract_strings(&script, gamedef).is_err());
+        let limited = extract_strings_limited(&script, gamedef, 2).unwrap();
+        assert_eq!(limited.len(), 2);
+        assert_eq!(limited[1].text, "Okarin");
+    }
+
+    #[test]
+    fn extract_with_layout_terminator() {
+        use crate::format::{Format, ScriptLayout, Scx};
+
+        let def = || gamedef::test_def(" ElPsyKongrOaki\u{3000}", "");
+        let (standard, alternate) = (
+            def(),
+            def().with_script_layout(ScriptLayout {
+                terminator: 0xFE,
+                ..Scx::layout()
+            }),
+        );
+        let lines = ["El Psy Kongroo", "Okarin"];
+        let script = build_scx(&lines, &standard);
+        // Same strings, ended by 0xFE
+        let mut alt_script = script.clone();
+        for handle in format::read_string_index(&script).unwrap().iter() {
+            alt_script[handle.0.end as usize - 1] = 0xFE;
+        }
+
+        for (script, def) in &[(&script, &standard), (&alt_script, &alternate)] {
+            let strings = extract_strings(script, def).unwrap();
+            let text: Vec<_> = strings.iter().map(|s| s.text.as_str()).collect();
+            assert_eq!(text, lines);
+        }
+        assert!(extract_strings(&alt_script, &standard).is_err());
+    }
+
+    #[test]
+    fn extract_past_corrupt_strings() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let mut script = build_scx(&["El Psy Kongroo", "Okarin", "Tuturu"], gamedef);
+        // An unrecognized instruction at the start of the second string
+        let offset = extract_strings(&script, gamedef).unwrap()[1].offset;
+        script[offset as usize] = 0x07;
+
+        let results = extract_results(&script, gamedef).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().text, "El Psy Kongroo");
+        assert!(matches!(results[1], Err((o, Error::String(1, _))) if o == offset));
+        assert_eq!(results[2].as_ref().unwrap().text, "Tuturu");
+        assert!(extract_strings(&script, gamedef).is_err());
+    }
+
+    #[test]
+    fn stale_table_offsets() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let script = build_scx(&["El Psy Kongroo", "Okarin"], gamedef);
+        let strings = extract_strings(&script, gamedef).unwrap();
+        let table = vec![
+            (strings[1].offset, "Okarin".to_string()),
+            (strings[1].offset + 2, "Tuturu".to_string()),
+            (strings[0].offset, "El Psy Congroo".to_string()),
+        ];
+        assert_eq!(
+            orphan_offsets(&table, &script, gamedef).unwrap(),
+            vec![strings[1].offset + 2]
+        );
+    }
+
+    #[test]
+    fn reject_offsets_past_end() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let mut script = build_scx(&["El Psy Kongroo", "Okarin"], gamedef);
+        script[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            extract_strings(&script, gamedef),
+            Err(Error::Format(format::Error::CorruptedFile))
+        ));
+    }
+
+    #[test]
+    fn partial_translation_progress() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let source = build_scx(&["Tuturu", "[name]Okabe[line]Hello", "Bye"], gamedef);
+        let translated = build_scx(&["Tuturu", "[name]Okabe[line]Hallo", "Bye"], gamedef);
+
+        let progress = translation_progress(&source, &translated, gamedef).unwrap();
+        assert_eq!(
+            progress,
+            TranslationProgress {
+                total: 3,
+                changed: 1,
+                untranslated: 2
+            }
+        );
+        assert!((progress.percentage() - 100.0 / 3.0).abs() < 1e-9);
+
+        let truncated = build_scx(&["Tuturu"], gamedef);
+        assert!(translation_progress(&source, &truncated, gamedef).is_err());
+    }
+
+    #[test]
+    fn collect_used_codepoints() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let a = build_scx(&["[name]Okabe[line]Hello"], gamedef);
+        let b = build_scx(&["ruby[ü]"], gamedef);
+        let used = used_codepoints(&[&a, &b], gamedef).unwrap();
+
+        let expected: BTreeSet<_> = ["OkabeHello", "ruby[ü]"]
+            .iter()
+            .flat_map(|s| text::encode_str(&text::Text(Cow::from(*s)), gamedef, false).unwrap())
+            .collect();
+        assert_eq!(used, expected);
+    }
+
+    #[test]
+    fn collect_unused_glyphs() {
+        let gamedef = gamedef::test_def(
+            " abc\u{E000}\u{E001}\u{E002}",
+            "[E000]=ü\n[E001]=ü\n[E002]=ë",
+        );
+        let script = build_scx(&["ab[ü]"], &gamedef);
+        let unused = unused_glyphs(&[&script], &gamedef).unwrap();
+        // Both glyphs for 'ü' count as used
+        let expected: BTreeSet<_> = [' ', 'c', '\u{E002}'].iter().cloned().collect();
+        assert_eq!(unused, expected);
+    }
+
+    #[test]
+    fn detect_game_by_charset() {
+        let sg0 = gamedef::get_by_alias("sg0").unwrap();
+        // Glyphs past the end of every other charset
+        let rare: String = sg0.charset()[6000..6004].iter().collect();
+        let script = build_scx(&[&format!("Okarin {}", rare), "Tuturu"], sg0);
+        let candidates = detect_game(&script);
+        assert!(std::ptr::eq(candidates[0].0, sg0));
+        assert_eq!(candidates[0].1, 1.0);
+        assert!(candidates[1].1 < 1.0);
+
+        // The sample script only uses glyphs common to the Steins;Gate games
+        let candidates = detect_game(&load_fixture("sghd", "sample.scx"));
+        assert_eq!(candidates[0].1, candidates[1].1);
+        let candidates = detect_game(&load_fixture("rn", "sample.msb"));
+        let aliases: Vec<_> = candidates.iter().map(|(def, _)| &*def.aliases[0]).collect();
+        assert_eq!(aliases, vec!["rn", "rnd"]);
+    }
+
+    #[test]
+    fn control_code_counts() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let script = load_fixture("sg0", "sample.scx");
+        let census = control_code_census(&script, gamedef).unwrap();
+        // A single name, followed by a line with ruby text
+        let expected: BTreeMap<_, _> = [0x01, 0x02, 0x09, 0x0A, 0x0B]
+            .iter()
+            .map(|op| (ControlCode(*op), 1))
+            .collect();
+        assert_eq!(census, expected);
+    }
+
+    #[test]
+    fn unmapped_reserved_codepoints() {
+        let gamedef =
+            gamedef::test_def_reserved(" a\u{E000}\u{E001}", "[E000]=ab", '\u{E000}'..='\u{E0FF}');
+        let script = build_scx(&["a[ab]", "\u{E001}a"], &gamedef);
+        assert_eq!(
+            reserved_collisions(&script, &gamedef).unwrap(),
+            vec![0x8003]
+        );
+
+        let script = build_scx(&["a[ab]"], &gamedef);
+        assert!(reserved_collisions(&script, &gamedef).unwrap().is_empty());
+    }
+
+    #[test]
+    fn find_text_control_boundaries() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let script = build_scx(
+            &["El Psy[linebreak]Kongroo", "El Psy Kongroo", "Tuturu"],
+            gamedef,
+        );
+        assert_eq!(
+            find_text(&script, gamedef, "PsyKongroo", false).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            find_text(&script, gamedef, "PsyKongroo", true).unwrap(),
+            vec![0]
+        );
+        assert_eq!(
+            find_text(&script, gamedef, "Kongroo", false).unwrap(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn dump_fixture() {
+        let gamedef = gamedef::get_by_alias("sg0").unwrap();
+        let dump = dump_script(&load_fixture("sg0", "sample.scx"), gamedef).unwrap();
+        let golden = load_fixture("sg0", "sample.dump");
+        assert_eq!(dump, std::str::from_utf8(&golden).unwrap());
+    }
+
+    #[test]
+    fn extract_fixtures() {
+        let mut scripts = Vec::new();
+        for (alias, name) in &[
+            ("sghd", "sample.scx"),
+            ("sghdzhs", "sample.scx"),
+            ("rn", "sample.msb"),
+            ("sglbp", "sample.scx"),
+            ("sg0", "sample.scx"),
+            ("sg0zhs", "sample.scx"),
+            ("rnd", "sample.msb"),
+        ] {
+            let gamedef = gamedef::get_by_alias(alias).unwrap();
+            let script = load_fixture(alias, name);
+            let strings = extract_strings(&script, gamedef).unwrap();
+            let text: Vec<_> = strings.iter().map(|s| s.text.as_str()).collect();
+            assert_eq!(
+                text,
+                vec![
+                    "Tuturu♪ Okarin。",
+                    "鳳凰院凶真",
+                    "[name]Mayuri[line]Okarin!"
+                ],
+                "{}",
+                alias
+            );
+            assert_eq!(strings[1].ruby, vec![(0..15, "Kyouma".to_string())]);
+            scripts.push(script);
+        }
+        // Each fixture is encoded with its own game's charset
+        let distinct: std::collections::HashSet<_> = scripts.iter().collect();
+        assert_eq!(distinct.len(), scripts.len());
+    }
+}
@@ -0,0 +1,12 @@
+//! Loader for the minimal sample scripts under `tests/fixtures/`.
+//! See `tests/fixtures/README.md` for the layout of each file.
+
+use std::{fs, path::PathBuf};
+
+/// Reads `tests/fixtures/<game>/<name>`, where `game` is the primary alias of a built-in game.
+pub fn load_fixture(game: &str, name: &str) -> Vec<u8> {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", game, name]
+        .iter()
+        .collect();
+    fs::read(&path).unwrap_or_else(|err| panic!("failed to load fixture {:?}: {}", path, err))
+}
@@ -4,11 +4,15 @@ use std::{
 };
 
 use crate::sc3;
-use byteorder::{LittleEndian, WriteBytesExt};
 use io::{Read, Seek, SeekFrom, Write};
 use nom::{
-    bytes::complete::tag, combinator::map, multi::many0, number::complete::le_u32,
-    sequence::preceded, sequence::tuple, IResult,
+    bytes::complete::{tag, take},
+    combinator::map,
+    multi::many0,
+    number::complete::le_u32,
+    sequence::preceded,
+    sequence::tuple,
+    IResult,
 };
 use sc3::Sc3String;
 
@@ -36,6 +40,68 @@ pub fn open(mut file: File) -> Result<Box<dyn MagesScript>, Error> {
     }
 }
 
+/// Reads the string index of a script held in memory.
+pub fn read_string_index(data: &[u8]) -> Result<StringIndex, Error> {
+    let magic = data.get(..4).ok_or(Error::UnrecognizedFormat)?;
+    let magic = std::str::from_utf8(magic).map_err(|_| Error::UnrecognizedFormat)?;
+
+    if magic == Scx::magic() {
+        parse_string_index::<Scx>(data)
+    } else if magic == Msb::magic() {
+        parse_string_index::<Msb>(data)
+    } else {
+        Err(Error::UnrecognizedFormat)
+    }
+}
+
+fn parse_string_index<F: Format>(data: &[u8]) -> Result<StringIndex, Error> {
+    parse_string_index_with(data, &F::layout(), F::str_index_location)
+}
+
+/// Parses the location of the string index from a script header. See `Format`.
+type StrIndexLocator = fn(&[u8]) -> IResult<&[u8], Range<u32>>;
+
+/// Reads the string index of a script held in memory, locating it with `str_index_location`
+/// and reading its entries as laid out by `layout`.
+fn parse_string_index_with(
+    data: &[u8],
+    layout: &ScriptLayout,
+    str_index_location: StrIndexLocator,
+) -> Result<StringIndex, Error> {
+    let (_, str_index_loc) = str_index_location(data).map_err(|_| Error::UnrecognizedFormat)?;
+    let buf = data
+        .get(str_index_loc.start as usize..str_index_loc.end as usize)
+        .ok_or(Error::CorruptedFile)?;
+    let (_, str_index_entries) = layout.str_index(buf).map_err(|_| Error::CorruptedFile)?;
+    let seek_from = match layout.offsets_from {
+        StrSeekOrigin::FileStart => 0,
+        StrSeekOrigin::HeapStart => str_index_loc.end,
+    };
+
+    check_string_offsets(&str_index_entries, seek_from, data.len() as u64)?;
+    Ok(StringIndex::new(
+        str_index_entries,
+        seek_from,
+        data.len() as u32,
+    ))
+}
+
+/// Rejects string offsets that point past the end of the script, so that a corrupted
+/// index fails early rather than producing bogus string ranges.
+fn check_string_offsets(
+    entries: &[StringIndexEntry],
+    seek_from: u32,
+    eof: u64,
+) -> Result<(), Error> {
+    let out_of_bounds = entries
+        .iter()
+        .any(|entry| entry.offset as u64 + seek_from as u64 > eof);
+    if out_of_bounds {
+        return Err(Error::CorruptedFile);
+    }
+    Ok(())
+}
+
 pub trait MagesScript {
     fn string_index(&self) -> &StringIndex;
     fn read_string<'a>(&self, handle: StringHandle) -> io::Result<Sc3String<'a>>;
@@ -50,14 +116,102 @@ pub struct Script<F: Format> {
     phantom: PhantomData<F>,
 }
 
+/// Describes how a script format lays out its string table.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ScriptLayout {
+    pub magic: &'static str,
+    /// Width of a string offset in bytes.
+    pub pointer_width: usize,
+    /// Width of a string index entry in bytes, which holds a string id besides the offset
+    /// if wider than `pointer_width`.
+    pub entry_width: usize,
+    pub little_endian: bool,
+    /// What string offsets are relative to.
+    pub offsets_from: StrSeekOrigin,
+    /// Opcode ending each string.
+    pub terminator: u8,
+    /// Byte marking the character code after it as text. See `sc3::Framing::escape`.
+    pub escape: Option<u8>,
+    /// Added to a charset index to form the character code stored in strings.
+    pub char_code_base: u16,
+}
+
+impl ScriptLayout {
+    /// Reads the string index of a script held in memory, which has to be of this layout.
+    /// The index is located through the header of the script format with the layout's magic.
+    pub fn read_string_index(&self, data: &[u8]) -> Result<StringIndex, Error> {
+        if !data.starts_with(self.magic.as_bytes()) {
+            return Err(Error::UnrecognizedFormat);
+        }
+        let str_index_location = if self.magic == Scx::magic() {
+            Scx::str_index_location
+        } else if self.magic == Msb::magic() {
+            Msb::str_index_location
+        } else {
+            return Err(Error::UnrecognizedFormat);
+        };
+        parse_string_index_with(data, self, str_index_location)
+    }
+
+    /// Returns how strings of this layout end.
+    pub fn framing(&self) -> sc3::Framing {
+        sc3::Framing {
+            terminator: self.terminator,
+            escape: self.escape,
+        }
+    }
+
+    /// Parses string index entries. An entry wider than a pointer holds the string id in the
+    /// bytes before the offset.
+    pub fn str_index<'a>(&self, i: &'a [u8]) -> IResult<&'a [u8], Vec<StringIndexEntry>> {
+        let id_width = self.entry_width - self.pointer_width;
+        many0(map(take(self.entry_width), move |entry: &[u8]| {
+            let (id, offset) = entry.split_at(id_width);
+            StringIndexEntry::new(self.read_uint(id), self.read_uint(offset))
+        }))(i)
+    }
+
+    /// Writes a string offset over the entry at the writer's position, leaving the id alone.
+    pub fn write_offset(&self, offset: u32, writer: &mut (impl Seek + Write)) -> io::Result<()> {
+        let id_width = self.entry_width - self.pointer_width;
+        if id_width > 0 {
+            writer.seek(SeekFrom::Current(id_width as i64))?;
+        }
+        if self.little_endian {
+            writer.write_all(&offset.to_le_bytes()[..self.pointer_width])
+        } else {
+            writer.write_all(&offset.to_be_bytes()[4 - self.pointer_width..])
+        }
+    }
+
+    fn read_uint(&self, bytes: &[u8]) -> u32 {
+        let push_byte = |acc: u32, b: &u8| acc << 8 | *b as u32;
+        if self.little_endian {
+            bytes.iter().rev().fold(0, push_byte)
+        } else {
+            bytes.iter().fold(0, push_byte)
+        }
+    }
+}
+
 pub trait Format {
-    fn magic() -> &'static str;
+    fn layout() -> ScriptLayout;
+    fn magic() -> &'static str {
+        Self::layout().magic
+    }
     fn str_index_location(header: &[u8]) -> IResult<&[u8], Range<u32>>;
-    fn str_index(i: &[u8]) -> IResult<&[u8], Vec<StringIndexEntry>>;
-    fn str_seek_origin() -> StrSeekOrigin;
-    fn write_offset(offset: u32, writer: &mut (impl Seek + Write)) -> io::Result<()>;
+    fn str_index(i: &[u8]) -> IResult<&[u8], Vec<StringIndexEntry>> {
+        Self::layout().str_index(i)
+    }
+    fn str_seek_origin() -> StrSeekOrigin {
+        Self::layout().offsets_from
+    }
+    fn write_offset(offset: u32, writer: &mut (impl Seek + Write)) -> io::Result<()> {
+        Self::layout().write_offset(offset, writer)
+    }
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum StrSeekOrigin {
     FileStart,
     HeapStart,
@@ -69,6 +223,12 @@ impl StringHandle {
     pub fn size(&self) -> usize {
         self.0.len()
     }
+
+    /// Returns the bytes of the string within an in-memory script.
+    pub fn slice<'a>(&self, data: &'a [u8]) -> Result<&'a [u8], Error> {
+        data.get(self.0.start as usize..self.0.end as usize)
+            .ok_or(Error::CorruptedFile)
+    }
 }
 pub struct StringIndex {
     entries: Vec<StringIndexEntry>,
@@ -101,6 +261,12 @@ impl<F: Format> Script<F> {
         let (_, str_index_loc) =
             F::str_index_location(&header).map_err(|_| Error::UnrecognizedFormat)?;
 
+        let eof = reader.seek(SeekFrom::End(0))?;
+        // Don't trust the header with the size of the allocation
+        if str_index_loc.end as u64 > eof {
+            return Err(Error::CorruptedFile);
+        }
+
         reader.seek(SeekFrom::Start(str_index_loc.start as u64))?;
         let mut buf = vec![0u8; str_index_loc.len()];
         reader.read_exact(&mut buf)?;
@@ -109,7 +275,7 @@ impl<F: Format> Script<F> {
             StrSeekOrigin::FileStart => 0,
             StrSeekOrigin::HeapStart => str_index_loc.end,
         };
-        let eof = reader.seek(SeekFrom::End(0))?;
+        check_string_offsets(&str_index_entries, seek_from, eof)?;
 
         let writer = BufWriter::new(file.try_clone()?);
 
@@ -229,8 +395,17 @@ impl Iterator for StringIndexIter<'_> {
 pub struct Scx {}
 
 impl Format for Scx {
-    fn magic() -> &'static str {
-        "SC3\0"
+    fn layout() -> ScriptLayout {
+        ScriptLayout {
+            magic: "SC3\0",
+            pointer_width: 4,
+            entry_width: 4,
+            little_endian: true,
+            offsets_from: StrSeekOrigin::FileStart,
+            terminator: 0xFF,
+            escape: None,
+            char_code_base: 0x8000,
+        }
     }
 
     fn str_index_location(header: &[u8]) -> IResult<&[u8], Range<u32>> {
@@ -239,25 +414,22 @@ impl Format for Scx {
             |(start, end)| start..end,
         )(header)
     }
-
-    fn str_index(i: &[u8]) -> IResult<&[u8], Vec<StringIndexEntry>> {
-        many0(map(le_u32, |offset| StringIndexEntry::new(0, offset)))(i)
-    }
-
-    fn str_seek_origin() -> StrSeekOrigin {
-        StrSeekOrigin::FileStart
-    }
-
-    fn write_offset(offset: u32, writer: &mut (impl Seek + Write)) -> io::Result<()> {
-        writer.write_u32::<LittleEndian>(offset)
-    }
 }
 
 pub struct Msb {}
 
 impl Format for Msb {
-    fn magic() -> &'static str {
-        "MES\0"
+    fn layout() -> ScriptLayout {
+        ScriptLayout {
+            magic: "MES\0",
+            pointer_width: 4,
+            entry_width: 8,
+            little_endian: true,
+            offsets_from: StrSeekOrigin::HeapStart,
+            terminator: 0xFF,
+            escape: None,
+            char_code_base: 0x8000,
+        }
     }
 
     fn str_index_location(header: &[u8]) -> IResult<&[u8], Range<u32>> {
@@ -266,21 +438,6 @@ impl Format for Msb {
             |(_, _, end)| 16..end,
         )(header)
     }
-
-    fn str_index(i: &[u8]) -> IResult<&[u8], Vec<StringIndexEntry>> {
-        many0(map(tuple((le_u32, le_u32)), |(id, offset)| {
-            StringIndexEntry::new(id, offset)
-        }))(i)
-    }
-
-    fn str_seek_origin() -> StrSeekOrigin {
-        StrSeekOrigin::HeapStart
-    }
-
-    fn write_offset(offset: u32, writer: &mut (impl Seek + Write)) -> io::Result<()> {
-        writer.seek(SeekFrom::Current(4))?;
-        writer.write_u32::<LittleEndian>(offset)
-    }
 }
 
 impl From<io::Error> for Error {
@@ -0,0 +1,27 @@
+//! Entry points for the fuzz targets in `fuzz/`, which cannot reach the private modules.
+//! Each one feeds untrusted bytes to a parser, discarding the result: only panics matter.
+
+use crate::extract;
+use crate::gamedef::{self, Game, GameDef};
+
+/// Parses `data` as the `compound_chars.map` of a runtime game pack.
+pub fn compound_map(data: &[u8]) {
+    // Covers the usual PUA range, so that mappings get as far as the encoding maps
+    let charset: String = std::iter::once(' ')
+        .chain('\u{E000}'..='\u{E0FF}')
+        .collect();
+    let _ = GameDef::from_parts(
+        Game::Custom,
+        "fuzz",
+        &["fuzz"],
+        None,
+        Vec::new(),
+        charset.as_bytes(),
+        data,
+    );
+}
+
+/// Extracts the strings of `data` as a Steins;Gate 0 script.
+pub fn extract(data: &[u8]) {
+    let _ = extract::extract_strings(data, gamedef::get(Game::SteinsGate0));
+}
@@ -1,103 +1,385 @@
-use crate::text::EncodingMaps;
+use crate::format::{Format, Msb, ScriptLayout, Scx};
+use crate::layout::{self, FontWidths};
+use crate::sc3;
+use crate::text::{self, Char, EncodingMaps, Text};
+use crate::warnings::{Warning, Warnings};
 use itertools::Itertools;
 use nom::{
     bytes::complete::is_not,
-    character::complete::{char, line_ending, not_line_ending},
-    combinator::{map, map_opt, map_res, opt},
-    multi::separated_list0,
+    character::complete::{char, not_line_ending},
+    combinator::{all_consuming, map, map_opt, map_res, opt, verify},
     sequence::{delimited, pair, preceded, tuple},
     IResult,
 };
 use rust_embed::RustEmbed;
-use std::{borrow::Cow, collections::HashMap, ops::RangeInclusive};
+use std::collections::BTreeSet;
+use std::{borrow::Cow, collections::HashMap, error, fmt, ops::RangeInclusive, path::PathBuf};
 use std::fs::File;
 use std::io::Write;
 
+/// Number of glyphs per bank, unless the charset declares otherwise.
+pub const DEFAULT_BANK_SIZE: usize = 64;
+/// Number of index bits per charset page, unless the charset declares otherwise.
+pub const DEFAULT_PAGE_SHIFT: u32 = 12;
+/// Number of glyphs addressable by a character code.
+const MAX_GLYPHS: usize = 0x8000;
+
 #[derive(RustEmbed)]
 #[folder = "resources/"]
 struct ResourceDir;
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq)]
+pub struct MergeConflict {
+    pub codepoint: char,
+    pub base: String,
+    pub overlay: String,
+}
+
+impl error::Error for MergeConflict {}
+
+#[derive(Debug)]
+pub enum GameDefError {
+    MissingResource(String),
+    InvalidUtf8(&'static str),
+    InvalidCharsetHeader(String),
+    EmptyCharset,
+    PageOverflow(usize),
+    CompoundMap(CompoundMapError),
+    MissingPuaChars(Vec<char>),
+    InvalidAlias(String),
+    /// The first slot of the charset holds something other than a null glyph.
+    InvalidNullGlyph(char),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum CompoundMapError {
+    Malformed(usize),
+    CyclicReference(char),
+    UndefinedReference(char),
+    /// The map holds more mappings than the limit it was parsed with.
+    TooManyMappings(usize),
+}
+
+/// The hand-authored contents of a game definition.
+pub struct GameDefManifest {
+    pub full_name: String,
+    pub aliases: Vec<String>,
+    pub reserved_codepoints: Option<RangeInclusive<char>>,
+    /// Contents of `charset.utf8`.
+    pub charset: String,
+    /// Contents of `compound_chars.map`.
+    pub compound_chars: String,
+}
+
+/// A problem found by `validate_manifest`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum ManifestIssue {
+    InvalidCharsetHeader(String),
+    EmptyCharset,
+    PageOverflow(usize),
+    InvalidNullGlyph(char),
+    DuplicateGlyph(char),
+    CompoundMap(CompoundMapError),
+    UnresolvedPuaChar(char),
+    /// An ordinary glyph of the charset lies in the range reserved for compound characters.
+    ReservedOverlap(char),
+    /// The reserved range extends beyond the BMP Private Use Area.
+    ReservedOutsidePua(RangeInclusive<char>),
+}
+
+/// A problem with the embedded data of a built-in game, found by `self_check`.
+#[derive(Debug)]
+pub enum SelfCheckIssue {
+    Construction(GameDefError),
+    Manifest(ManifestIssue),
+    Warning(Warning),
+    /// A glyph that does not decode back to itself once encoded, by character code.
+    Roundtrip(u16),
+}
+
+impl error::Error for GameDefError {}
+impl error::Error for CompoundMapError {}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Game {
     SteinsGateHD,
+    SteinsGateHDZhs,
     RoboticsNotes,
     SteinsGatePhenogram,
     SteinsGate0,
+    SteinsGate0Zhs,
     RoboticsNotesDash,
+    /// A game pack loaded from the filesystem at runtime.
+    Custom,
+}
+
+/// The games with resources embedded into the binary.
+const BUILTIN_GAMES: &[BuiltinGame] = &[
+    BuiltinGame {
+        game: Game::SteinsGateHD,
+        full_name: "Steins;Gate Steam",
+        resource_dir: "sghd",
+        aliases: &["sghd", "steinsgatehd"],
+        reserved_codepoints: None,
+        fullwidth_blocklist: &['\'', '-', '[', ']', '(', ')'],
+        default_fullwidth: false,
+    },
+    BuiltinGame {
+        game: Game::SteinsGateHDZhs,
+        full_name: "Steins;Gate Steam (Simplified Chinese)",
+        resource_dir: "sghdzhs",
+        aliases: &["sghdzhs", "steinsgatehdzhs"],
+        reserved_codepoints: Some('\u{E12F}'..='\u{E2AF}'),
+        fullwidth_blocklist: &['\''],
+        default_fullwidth: true,
+    },
+    BuiltinGame {
+        game: Game::RoboticsNotes,
+        full_name: "Robotics;Notes",
+        resource_dir: "rn",
+        aliases: &["rn", "roboticsnotes"],
+        reserved_codepoints: None,
+        fullwidth_blocklist: &['\'', '-', '[', ']', '(', ')'],
+        default_fullwidth: false,
+    },
+    BuiltinGame {
+        game: Game::SteinsGatePhenogram,
+        full_name: "Steins;Gate: Linear Bounded Phenogram",
+        resource_dir: "sglbp",
+        aliases: &["sglbp", "steinsgatelbp"],
+        reserved_codepoints: None,
+        fullwidth_blocklist: &['\'', '-', '[', ']', '(', ')'],
+        default_fullwidth: false,
+    },
+    BuiltinGame {
+        game: Game::SteinsGate0,
+        full_name: "Steins;Gate 0",
+        resource_dir: "sg0",
+        aliases: &["sg0", "steinsgate0"],
+        reserved_codepoints: None,
+        fullwidth_blocklist: &['\''],
+        default_fullwidth: false,
+    },
+    BuiltinGame {
+        game: Game::SteinsGate0Zhs,
+        full_name: "Steins;Gate 0 (Simplified Chinese)",
+        resource_dir: "sg0zhs",
+        aliases: &["sg0zhs", "steinsgate0zhs"],
+        reserved_codepoints: Some('\u{E12F}'..='\u{E2AF}'),
+        fullwidth_blocklist: &['\''],
+        default_fullwidth: true,
+    },
+    BuiltinGame {
+        game: Game::RoboticsNotesDash,
+        full_name: "Robotics;Notes DaSH",
+        resource_dir: "rnd",
+        aliases: &["rnd", "roboticsnotesdash"],
+        reserved_codepoints: None,
+        fullwidth_blocklist: &['\''],
+        default_fullwidth: false,
+    },
+];
+
+/// The arguments `GameDef::try_new` is called with for a built-in game.
+struct BuiltinGame {
+    game: Game,
+    full_name: &'static str,
+    resource_dir: &'static str,
+    aliases: &'static [&'static str],
+    reserved_codepoints: Option<RangeInclusive<char>>,
+    fullwidth_blocklist: &'static [char],
+    default_fullwidth: bool,
+}
+
+impl BuiltinGame {
+    fn try_build(&self) -> Result<GameDef, GameDefError> {
+        GameDef::try_new(
+            self.game,
+            self.full_name,
+            self.resource_dir,
+            self.aliases,
+            self.reserved_codepoints.clone(),
+            self.fullwidth_blocklist.to_vec(),
+            self.default_fullwidth,
+        )
+    }
 }
 
 lazy_static! {
-    pub static ref DEFS: Vec<GameDef> = vec![
-        GameDef::new(
-            Game::SteinsGateHD,
-            "Steins;Gate Steam",
-            "sghd",
-            &["sghd", "steinsgatehd"],
-            None,
-            vec!['\'', '-', '[', ']', '(', ')']
-        ),
-        GameDef::new(
-            Game::SteinsGate0,
-            "Steins;Gate Steam (Simplified Chinese)",
-            "sghdzhs",
-            &["sghdzhs", "steinsgatehdzhs"],
-            Some('\u{E12F}'..='\u{E2AF}'),
-            vec!['\'']
-        ),              
-        GameDef::new(
-            Game::RoboticsNotes,
-            "Robotics;Notes",
-            "rn",
-            &["rn", "roboticsnotes"],
-            None,
-            vec!['\'', '-', '[', ']', '(', ')']
-        ),
-        GameDef::new(
-            Game::SteinsGatePhenogram,
-            "Steins;Gate: Linear Bounded Phenogram",
-            "sglbp",
-            &["sglbp", "steinsgatelbp"],
-            None,
-            vec!['\'', '-', '[', ']', '(', ')']
-        ),
-        GameDef::new(
-            Game::SteinsGate0,
-            "Steins;Gate 0",
-            "sg0",
-            &["sg0", "steinsgate0"],
-            None,
-            vec!['\'']
-        ),
-        GameDef::new(
-            Game::SteinsGate0,
-            "Steins;Gate 0 (Simplified Chinese)",
-            "sg0zhs",
-            &["sg0zhs", "steinsgate0zhs"],
-            Some('\u{E12F}'..='\u{E2AF}'),
-            vec!['\'']
-        ),        
-        GameDef::new(
-            Game::RoboticsNotesDash,
-            "Robotics;Notes DaSH",
-            "rnd",
-            &["rnd", "roboticsnotesdash"],
-            None,
-            vec!['\'']
-        ),
-    ];
+    pub static ref DEFS: Vec<GameDef> = BUILTIN_GAMES
+        .iter()
+        .map(|builtin| GameDef::new(
+            builtin.game,
+            builtin.full_name,
+            builtin.resource_dir,
+            builtin.aliases,
+            builtin.reserved_codepoints.clone(),
+            builtin.fullwidth_blocklist.to_vec(),
+            builtin.default_fullwidth,
+        ))
+        .collect();
+}
+
+/// Where the resources of a `GameDef` were loaded from.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum GameDefSource {
+    /// Resources embedded into the binary, identified by their resource directory.
+    Embedded(&'static str),
+    /// A resource pack on the filesystem.
+    Directory(PathBuf),
+    /// Resources supplied directly by the caller.
+    Manifest,
 }
 
 pub struct GameDef {
-    #[allow(dead_code)]
     game: Game,
-    pub full_name: &'static str,
-    pub aliases: &'static [&'static str],
-    #[allow(dead_code)]
+    source: GameDefSource,
+    pub full_name: Cow<'static, str>,
+    pub aliases: Vec<Cow<'static, str>>,
     reserved_codepoints: Option<RangeInclusive<char>>,
     charset: Vec<char>,
+    source_lines: Vec<Option<usize>>,
+    layout: CharsetLayout,
     pub compound_chars: HashMap<char, String>,
     pub encoding_maps: EncodingMaps,
     pub fullwidth_blocklist: Vec<char>,
+    default_fullwidth: bool,
+    thin_space: Option<u16>,
+    max_record_codepoints: Option<usize>,
+    confusable_overrides: HashMap<char, char>,
+    script_layout: Option<ScriptLayout>,
+    font_widths: HashMap<String, FontWidths>,
+    warnings: Warnings,
+}
+
+/// A character that cannot be encoded, but looks like one that can. See `GameDef::find_confusables`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConfusableHit {
+    /// Byte offset of the character in the text.
+    pub offset: usize,
+    pub found: char,
+    pub suggestion: char,
+}
+
+/// What the first slot of a charset may hold. The scanner keeps a space there, while it turns
+/// spaces in any other slot into padding.
+pub const NULL_GLYPHS: &[char] = &[' ', '\0'];
+
+/// How many mappings a compound character map may hold by default, counting each codepoint
+/// of a range. Far more than the Private Use Area has room for.
+pub const DEFAULT_MAX_COMPOUND_MAPPINGS: usize = 0x10000;
+
+/// Name of the font dialogue is displayed in. Fonts without a width table of their own are
+/// measured with its widths.
+pub const DIALOGUE_FONT: &str = "dialogue";
+
+/// The Private Use Area of the Basic Multilingual Plane, where reserved ranges belong.
+pub const BMP_PUA: RangeInclusive<char> = '\u{E000}'..='\u{F8FF}';
+
+/// Cyrillic and Greek letters that are indistinguishable from Latin ones in most fonts.
+const CONFUSABLES: &[(char, char)] = &[
+    ('а', 'a'),
+    ('е', 'e'),
+    ('і', 'i'),
+    ('ј', 'j'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('с', 'c'),
+    ('ѕ', 's'),
+    ('у', 'y'),
+    ('х', 'x'),
+    ('А', 'A'),
+    ('В', 'B'),
+    ('Е', 'E'),
+    ('І', 'I'),
+    ('Ј', 'J'),
+    ('К', 'K'),
+    ('М', 'M'),
+    ('Н', 'H'),
+    ('О', 'O'),
+    ('Р', 'P'),
+    ('С', 'C'),
+    ('Ѕ', 'S'),
+    ('Т', 'T'),
+    ('Х', 'X'),
+    ('Υ', 'Y'),
+    ('Α', 'A'),
+    ('Β', 'B'),
+    ('Ε', 'E'),
+    ('Ζ', 'Z'),
+    ('Η', 'H'),
+    ('Ι', 'I'),
+    ('Κ', 'K'),
+    ('Μ', 'M'),
+    ('Ν', 'N'),
+    ('Ο', 'O'),
+    ('Ρ', 'P'),
+    ('Τ', 'T'),
+    ('Χ', 'X'),
+    ('ο', 'o'),
+    ('ν', 'v'),
+];
+
+/// The status of a slot in the reserved codepoint range.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SlotStatus {
+    Free,
+    Compound(String),
+}
+
+/// What a Private Use Area character stands for in a game. See `GameDef::pua_kind`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PuaKind {
+    /// A custom glyph of the font, which decodes as is.
+    LiteralGlyph,
+    /// A compound character, which decodes to its expansion.
+    CompoundExpansion,
+    /// A character of the reserved range that the charset has no glyph for.
+    EngineReserved,
+}
+
+/// The parsed contents of a resource directory.
+struct Resources {
+    charset: Vec<char>,
+    source_lines: Vec<Option<usize>>,
+    layout: CharsetLayout,
+    compound_chars: HashMap<char, String>,
+    encoding_maps: EncodingMaps,
+}
+
+impl Resources {
+    fn parse(
+        charset: &[u8],
+        compound_chars: &[u8],
+        max_compound_mappings: usize,
+    ) -> Result<Self, GameDefError> {
+        let charset =
+            std::str::from_utf8(charset).map_err(|_| GameDefError::InvalidUtf8("charset.utf8"))?;
+        let (layout, body) = parse_charset_header(charset)?;
+        let first_line = charset[..charset.len() - body.len()].matches('\n').count() + 1;
+        let scanned = scan_pages(body, &layout, first_line)?;
+        let (charset, source_lines) = (scanned.glyphs, scanned.lines);
+        if charset.iter().all(|ch| *ch == '\0' || *ch == ' ') {
+            return Err(GameDefError::EmptyCharset);
+        }
+        if !NULL_GLYPHS.contains(&charset[0]) {
+            return Err(GameDefError::InvalidNullGlyph(charset[0]));
+        }
+
+        let compound_chars = std::str::from_utf8(compound_chars)
+            .map_err(|_| GameDefError::InvalidUtf8("compound_chars.map"))?;
+        let compound_chars = parse_compound_ch_map_limited(compound_chars, max_compound_mappings)?;
+        let encoding_maps = EncodingMaps::new(&charset, &compound_chars)
+            .map_err(|err| GameDefError::MissingPuaChars(err.missing_pua_chars))?;
+
+        Ok(Self {
+            charset,
+            source_lines,
+            layout,
+            compound_chars,
+            encoding_maps,
+        })
+    }
 }
 
 impl GameDef {
@@ -108,152 +390,2301 @@ impl GameDef {
         aliases: &'static [&'static str],
         reserved_codepoints: Option<RangeInclusive<char>>,
         fullwidth_blocklist: Vec<char>,
+        default_fullwidth: bool,
     ) -> Self {
-        fn file_path(resource_dir: &'static str, name: &'static str) -> String {
-            format!("{}/{}", resource_dir, name)
-        }
-
-        let _charset: Cow<[u8]> =
-            ResourceDir::get(&file_path(resource_dir, "charset.utf8")).unwrap();
-        let _charset = std::str::from_utf8(_charset.as_ref());
-        let _charset = _charset.as_ref().expect("screw rust man").replace("\r",""); // Or save the file with LF line endings
-        let _charset: Vec<char> = _charset
-            .chars()            
-            .collect();                            
-        let mut charset = Vec::<char>::new();
-        {
-            let mut i = 0 as usize;
-            let mut j = 0 as usize;
-            while j < _charset.len() {
-                let mut nl = 0 as usize;
-                while j < _charset.len() && _charset[j] == '\n' {                    
-                    i = (i + 64 - 1) & !(64 - 1); // align upward to 64
-                    i += nl;
-                    j += 1;
-                    nl = 1;
-                }
-                charset.resize(i + 1, '\0');
-                if (j < _charset.len()) {
-                    charset[i] = _charset[j];
-                    if (i != 0 && charset[i] == ' ') {
-                        charset[i] = '\0';
-                    }
-                }
-                i += 1; j += 1;
-            }
-        }
-        let compound_chars: Cow<[u8]> =
-            ResourceDir::get(&file_path(resource_dir, "compound_chars.map")).unwrap();
-        let compound_chars = std::str::from_utf8(compound_chars.as_ref()).unwrap();
-        let compound_chars = parse_compound_ch_map(compound_chars);
-        let encoding_maps = EncodingMaps::new(&charset, &compound_chars);
-
-        if let Err(err) = encoding_maps {
-            panic!(
-                "Error while constructing encoding maps for {}. \
-                The following Private Use Area characters were not found in the charset: [{}]",
-                full_name,
-                err.missing_pua_chars
-                    .into_iter()
-                    .map(|ch| format!("'{}'", ch.escape_unicode()))
-                    .join(", ")
-            );
+        Self::try_new(
+            game,
+            full_name,
+            resource_dir,
+            aliases,
+            reserved_codepoints,
+            fullwidth_blocklist,
+            default_fullwidth,
+        )
+        .unwrap_or_else(|err| panic!("Error while constructing {}: {}", full_name, err))
+    }
+
+    pub fn try_new(
+        game: Game,
+        full_name: &'static str,
+        resource_dir: &'static str,
+        aliases: &'static [&'static str],
+        reserved_codepoints: Option<RangeInclusive<char>>,
+        fullwidth_blocklist: Vec<char>,
+        default_fullwidth: bool,
+    ) -> Result<Self, GameDefError> {
+        fn resource(
+            resource_dir: &'static str,
+            name: &'static str,
+        ) -> Result<Cow<'static, [u8]>, GameDefError> {
+            let path = format!("{}/{}", resource_dir, name);
+            ResourceDir::get(&path).ok_or(GameDefError::MissingResource(path))
         }
 
+        let charset = resource(resource_dir, "charset.utf8")?;
+        let compound_chars = resource(resource_dir, "compound_chars.map")?;
+        let resources = Resources::parse(&charset, &compound_chars, DEFAULT_MAX_COMPOUND_MAPPINGS)?;
+
+        let mut warnings = check_fullwidth_blocklist(&fullwidth_blocklist);
+        check_compound_expansions(
+            &resources.compound_chars,
+            reserved_codepoints.as_ref(),
+            &mut warnings,
+        );
+        Ok(Self {
+            game,
+            source: GameDefSource::Embedded(resource_dir),
+            full_name: Cow::Borrowed(full_name),
+            aliases: aliases.iter().map(|alias| Cow::Borrowed(*alias)).collect(),
+            reserved_codepoints,
+            charset: resources.charset,
+            source_lines: resources.source_lines,
+            layout: resources.layout,
+            compound_chars: resources.compound_chars,
+            encoding_maps: resources.encoding_maps,
+            warnings,
+            fullwidth_blocklist,
+            default_fullwidth,
+            thin_space: None,
+            max_record_codepoints: None,
+            confusable_overrides: HashMap::new(),
+            script_layout: None,
+            font_widths: HashMap::new(),
+        })
+    }
+
+    /// Builds a definition from the raw contents of `charset.utf8` and `compound_chars.map`.
+    /// The resulting definition reports `GameDefSource::Manifest` as its source.
+    pub fn from_parts(
+        game: Game,
+        full_name: &'static str,
+        aliases: &'static [&'static str],
+        reserved_codepoints: Option<RangeInclusive<char>>,
+        fullwidth_blocklist: Vec<char>,
+        charset: &[u8],
+        compound_chars: &[u8],
+    ) -> Result<Self, GameDefError> {
+        let resources = Resources::parse(charset, compound_chars, DEFAULT_MAX_COMPOUND_MAPPINGS)?;
+        Ok(Self::from_resources(
+            game,
+            Cow::Borrowed(full_name),
+            aliases.iter().map(|alias| Cow::Borrowed(*alias)).collect(),
+            reserved_codepoints,
+            fullwidth_blocklist,
+            resources,
+        ))
+    }
+
+    fn from_resources(
+        game: Game,
+        full_name: Cow<'static, str>,
+        aliases: Vec<Cow<'static, str>>,
+        reserved_codepoints: Option<RangeInclusive<char>>,
+        fullwidth_blocklist: Vec<char>,
+        resources: Resources,
+    ) -> Self {
+        let mut warnings = check_fullwidth_blocklist(&fullwidth_blocklist);
+        check_compound_expansions(
+            &resources.compound_chars,
+            reserved_codepoints.as_ref(),
+            &mut warnings,
+        );
         Self {
             game,
+            source: GameDefSource::Manifest,
             full_name,
             aliases,
             reserved_codepoints,
-            charset,
-            compound_chars,
-            encoding_maps: encoding_maps.unwrap(),
+            charset: resources.charset,
+            source_lines: resources.source_lines,
+            layout: resources.layout,
+            compound_chars: resources.compound_chars,
+            encoding_maps: resources.encoding_maps,
+            warnings,
             fullwidth_blocklist,
+            default_fullwidth: false,
+            thin_space: None,
+            max_record_codepoints: None,
+            confusable_overrides: HashMap::new(),
+            script_layout: None,
+            font_widths: HashMap::new(),
+        }
+    }
+
+    /// Loads a game pack from a directory holding `charset.utf8` and `compound_chars.map`.
+    /// The name of the directory doubles as the full name and the only alias of the game.
+    pub fn from_dir(dir: &std::path::Path) -> Result<Self, GameDefError> {
+        Self::from_dir_limited(dir, DEFAULT_MAX_COMPOUND_MAPPINGS)
+    }
+
+    /// Like `from_dir`, but accepts at most `max_compound_mappings` compound character
+    /// mappings, for loading packs from untrusted sources.
+    pub fn from_dir_limited(
+        dir: &std::path::Path,
+        max_compound_mappings: usize,
+    ) -> Result<Self, GameDefError> {
+        fn resource(dir: &std::path::Path, name: &str) -> Result<Vec<u8>, GameDefError> {
+            let path = dir.join(name);
+            std::fs::read(&path)
+                .map_err(|_| GameDefError::MissingResource(path.display().to_string()))
         }
+
+        let name = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| is_valid_alias(name))
+            .ok_or_else(|| GameDefError::InvalidAlias(dir.display().to_string()))?;
+
+        let charset = resource(dir, "charset.utf8")?;
+        let compound_chars = resource(dir, "compound_chars.map")?;
+        let resources = Resources::parse(&charset, &compound_chars, max_compound_mappings)?;
+        let def = Self::from_resources(
+            Game::Custom,
+            Cow::Owned(name.to_owned()),
+            vec![Cow::Owned(name.to_owned())],
+            None,
+            Vec::new(),
+            resources,
+        );
+        Ok(Self {
+            source: GameDefSource::Directory(dir.to_owned()),
+            ..def
+        })
+    }
+
+    pub fn game(&self) -> Game {
+        self.game
     }
 
     pub fn charset(&self) -> &[char] {
         &self.charset
     }
-}
 
-#[allow(dead_code)]
-pub fn get(game: Game) -> &'static GameDef {
-    DEFS.iter().find(|x| x.game == game).unwrap()
-}
+    /// Returns the glyph of the first charset slot. The engines reserve it for a null glyph,
+    /// which is one of `NULL_GLYPHS`: a space, or an empty glyph (`'\0'`).
+    pub fn null_glyph(&self) -> char {
+        self.charset[0]
+    }
 
-pub fn get_by_alias(alias: &str) -> Option<&'static GameDef> {
-    DEFS.iter().find(|x| x.aliases.contains(&alias))
-}
+    pub fn source(&self) -> &GameDefSource {
+        &self.source
+    }
 
-#[derive(Eq, PartialEq, Debug)]
-struct PuaMapping<'a> {
-    codepoint_range: RangeInclusive<char>,
-    ch: &'a str,
-}
+    /// Returns the line of `charset.utf8` that defines the glyph with the given character code.
+    pub fn source_line_of(&self, codepoint: u16) -> Option<usize> {
+        let i = self.glyph_index(codepoint)?;
+        self.source_lines.get(i as usize).cloned().flatten()
+    }
 
-impl<'a> PuaMapping<'a> {
-    fn new(codepoint_range: RangeInclusive<char>, ch: &'a str) -> Self {
-        Self {
-            codepoint_range,
-            ch,
+    /// Returns the glyph displayed for a character code, or `None` for codes that are
+    /// out of range, padding or compound characters.
+    pub fn codepoint_to_display_char(&self, codepoint: u16) -> Option<char> {
+        self.glyph_of(codepoint)
+            .filter(|ch| *ch != '\0' && !self.compound_chars.contains_key(ch))
+    }
+
+    /// Returns how many charset slots `b` lies after `a` (negative if before),
+    /// or `None` if either cannot be encoded.
+    pub fn index_distance(&self, a: char, b: char) -> Option<i32> {
+        let a = self.encoding_maps.index_of(a)?;
+        let b = self.encoding_maps.index_of(b)?;
+        Some(b as i32 - a as i32)
+    }
+
+    /// Tells whether a character code refers to a padding slot of the charset, or lies past its end.
+    pub fn is_padding(&self, codepoint: u16) -> bool {
+        self.glyph_of(codepoint).map_or(true, |ch| ch == '\0')
+    }
+
+    /// Returns the number of banks spanned by the charset, the last one possibly being partial.
+    pub fn bank_count(&self) -> u16 {
+        ((self.charset.len() + self.layout.bank_size - 1) / self.layout.bank_size) as u16
+    }
+
+    /// Renders the charset as a grid for viewing in a terminal: one row per bank, labeled with
+    /// the bank number and the index of its first slot. Every slot is two columns wide so that
+    /// the rows line up with wide glyphs, and padding slots show up as `·`.
+    pub fn charset_grid(&self) -> String {
+        let mut grid = String::new();
+        for (bank, glyphs) in self.charset.chunks(self.layout.bank_size).enumerate() {
+            let mut row = format!("{:>4} {:04X}  ", bank, bank * self.layout.bank_size);
+            for ch in glyphs {
+                let ch = if *ch == '\0' { '·' } else { *ch };
+                row.push(ch);
+                if layout::char_width(ch) == 1 {
+                    row.push(' ');
+                }
+            }
+            grid.push_str(row.trim_end());
+            grid.push('\n');
         }
+        grid
     }
 
-    pub fn parse(i: &str) -> IResult<&str, PuaMapping> {
-        fn codepoint(i: &str) -> IResult<&str, char> {
-            map_opt(
-                map_res(is_not("-]"), |s| u32::from_str_radix(s, 16)),
-                std::char::from_u32,
-            )(i)
+    /// Returns the layout of the game's script files. Game packs loaded at runtime may use
+    /// either layout, so it is only known for the built-in games, unless declared with
+    /// `with_script_layout`.
+    pub fn script_layout(&self) -> Option<ScriptLayout> {
+        if self.script_layout.is_some() {
+            return self.script_layout;
+        }
+        match self.game {
+            Game::RoboticsNotes | Game::RoboticsNotesDash => Some(Msb::layout()),
+            Game::Custom => None,
+            _ => Some(Scx::layout()),
         }
+    }
 
-        fn range(i: &str) -> IResult<&str, RangeInclusive<char>> {
-            map(
-                delimited(
-                    char('['),
-                    pair(codepoint, opt(preceded(char('-'), codepoint))),
-                    char(']'),
-                ),
-                |(a, b)| match (a, b) {
-                    (a, Some(b)) => (a..=b),
-                    _ => (a..=a),
-                },
-            )(i)
+    /// Classifies a Private Use Area character by what the charset and compound map make of it.
+    /// Returns `None` for characters outside the Private Use Area and for those the game
+    /// does not use.
+    pub fn pua_kind(&self, cp: char) -> Option<PuaKind> {
+        if !BMP_PUA.contains(&cp) {
+            None
+        } else if self.compound_chars.contains_key(&cp) {
+            Some(PuaKind::CompoundExpansion)
+        } else if self.charset.contains(&cp) {
+            Some(PuaKind::LiteralGlyph)
+        } else if self
+            .reserved_codepoints
+            .as_ref()
+            .map_or(false, |reserved| reserved.contains(&cp))
+        {
+            Some(PuaKind::EngineReserved)
+        } else {
+            None
         }
+    }
 
-        map(tuple((range, char('='), not_line_ending)), |(r, _, ch)| {
-            PuaMapping::new(r, ch)
-        })(i)
+    /// Returns the range of Private Use Area characters set aside for compound characters.
+    pub fn reserved_codepoints(&self) -> Option<&RangeInclusive<char>> {
+        self.reserved_codepoints.as_ref()
     }
-}
 
-fn parse_compound_ch_map(i: &str) -> HashMap<char, String> {
-    let mappings = separated_list0(line_ending, PuaMapping::parse)(i).unwrap().1;
-    mappings
-        .iter()
-        .flat_map(|m| {
-            m.codepoint_range
-                .clone()
-                .map(move |codepoint| (codepoint, m.ch.to_string()))
+    /// Lists every codepoint of the reserved range along with its status.
+    /// Games without a reserved range yield an empty list.
+    pub fn reserved_slot_status(&self) -> Vec<(char, SlotStatus)> {
+        self.reserved_codepoints
+            .clone()
+            .into_iter()
+            .flatten()
+            .map(|ch| match self.compound_chars.get(&ch) {
+                Some(expansion) => (ch, SlotStatus::Compound(expansion.clone())),
+                None => (ch, SlotStatus::Free),
+            })
+            .collect()
+    }
+
+    /// Counts the characters of `text` that converting to fullwidth would change,
+    /// which helps catch lines accidentally left halfwidth.
+    pub fn fullwidth_normalization_diff(&self, text: &str) -> usize {
+        Text(Cow::from(text))
+            .iter(&self.encoding_maps)
+            .filter(|ch| match ch {
+                Char::Regular(c) => {
+                    !self.fullwidth_blocklist.contains(c) && text::replace_halfwidth(*c) != *c
+                }
+                Char::Compound(_) => false,
+            })
+            .count()
+    }
+
+    /// Moves `text` to the given width form. Blocklisted characters are never fullwidth:
+    /// converting to fullwidth leaves them halfwidth, and turns their fullwidth variants
+    /// halfwidth. This makes the conversion idempotent, but not lossless, as fullwidth variants
+    /// of blocklisted characters do not survive a round trip.
+    pub fn normalize_width(&self, text: &str, target: text::WidthForm) -> String {
+        let text = Text(Cow::from(text));
+        let chars = text.iter(&self.encoding_maps).map(|ch| match ch {
+            Char::Regular(c) => {
+                let halfwidth = text::replace_fullwidth(c);
+                match target {
+                    text::WidthForm::Fullwidth
+                        if !self.fullwidth_blocklist.contains(&halfwidth) =>
+                    {
+                        Char::Regular(text::replace_halfwidth(halfwidth))
+                    }
+                    _ => Char::Regular(halfwidth),
+                }
+            }
+            ch => ch,
+        });
+        Text::from_chars(chars, true).0.into_owned()
+    }
+
+    /// Returns the characters needed to write `words` that the charset lacks.
+    pub fn missing_for(&self, words: &[&str]) -> BTreeSet<char> {
+        text::required_glyphs(words)
+            .into_iter()
+            .filter(|ch| self.encoding_maps.index_of(*ch).is_none())
+            .collect()
+    }
+
+    /// Counts how many characters of a Unicode block the charset can encode, returning
+    /// (covered, total).
+    pub fn charset_coverage_of_block(&self, block: RangeInclusive<char>) -> (usize, usize) {
+        block.fold((0, 0), |(covered, total), ch| {
+            match self.encoding_maps.index_of(ch) {
+                Some(_) => (covered + 1, total + 1),
+                None => (covered, total + 1),
+            }
         })
-        .collect()
-}
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Encodes every glyph of the charset after decoding it, returning the character codes of
+    /// those that do not decode back to the same character. Duplicate glyphs encode to the
+    /// last slot holding them, which still decodes to the same character.
+    pub fn verify_roundtrip(&self) -> Vec<u16> {
+        let options = text::DecodeOptions {
+            literal_unmapped_pua: true,
+            ..text::DecodeOptions::default()
+        };
+        let charset_len = self.charset.len() as u16;
+        (0..charset_len)
+            .map(|i| self.char_code(i))
+            .filter(|code| !self.is_padding(*code))
+            .filter(|code| {
+                let decode = |code| text::decode_char_with(code, self, &options);
+                match decode(*code) {
+                    Ok(ch) => text::encode_char(&ch, self)
+                        .ok()
+                        .and_then(|encoded| decode(encoded).ok())
+                        .map_or(true, |decoded| decoded != ch),
+                    Err(_) => true,
+                }
+            })
+            .collect()
+    }
 
-    #[test]
-    fn parse_pua_mapping() {
-        assert_eq!(
-            PuaMapping::parse("[E01C]=meow").unwrap().1,
-            PuaMapping::new('\u{E01C}'..='\u{E01C}', "meow")
-        );
+    /// Problems with the definition noticed during construction that do not prevent its use.
+    pub fn warnings(&self) -> &Warnings {
+        &self.warnings
+    }
 
-        assert_eq!(
-            PuaMapping::parse("[E01C-E01F]=¹⁸").unwrap().1,
-            PuaMapping::new('\u{E01C}'..='\u{E01F}', "¹⁸")
+    /// Declares the character code of a custom thin space glyph, which fan fonts can provide
+    /// for justifying lines. See `layout::wrap_to_width_with`.
+    pub fn with_thin_space(self, codepoint: u16) -> Self {
+        Self {
+            thin_space: Some(codepoint),
+            ..self
+        }
+    }
+
+    /// Declares the layout of the game's script files, overriding the built-in one.
+    /// See `script_layout`.
+    pub fn with_script_layout(self, layout: ScriptLayout) -> Self {
+        Self {
+            script_layout: Some(layout),
+            ..self
+        }
+    }
+
+    /// Returns how strings end in the game's scripts.
+    pub fn string_framing(&self) -> sc3::Framing {
+        self.script_layout()
+            .map_or(sc3::DEFAULT_FRAMING, |layout| layout.framing())
+    }
+
+    fn char_code_base(&self) -> u16 {
+        self.script_layout()
+            .map_or(sc3::DEFAULT_CHAR_CODE_BASE, |layout| layout.char_code_base)
+    }
+
+    /// Returns the bytes a charset index is stored as in the game's strings.
+    pub fn codepoint_to_disk_bytes(&self, cp: u16) -> [u8; 2] {
+        self.char_code_base().wrapping_add(cp).to_be_bytes()
+    }
+
+    /// Returns the charset index stored as `bytes`, or `None` if they are below the layout's
+    /// character code base and thus not a character.
+    pub fn disk_bytes_to_codepoint(&self, bytes: [u8; 2]) -> Option<u16> {
+        u16::from_be_bytes(bytes).checked_sub(self.char_code_base())
+    }
+
+    /// Returns the character code of the glyph at charset index `cp`, as held by
+    /// `StringToken::Text`.
+    pub fn char_code(&self, cp: u16) -> u16 {
+        u16::from_be_bytes(self.codepoint_to_disk_bytes(cp))
+    }
+
+    /// Returns the charset index of a character code. See `disk_bytes_to_codepoint`.
+    pub fn glyph_index(&self, code: u16) -> Option<u16> {
+        self.disk_bytes_to_codepoint(code.to_be_bytes())
+    }
+
+    /// Returns the charset glyph of a character code, or `None` if it lies outside the charset.
+    pub fn glyph_of(&self, code: u16) -> Option<char> {
+        self.glyph_index(code)
+            .and_then(|i| self.charset.get(i as usize))
+            .cloned()
+    }
+
+    /// Declares the glyph widths of one of the game's fonts, such as `DIALOGUE_FONT`.
+    pub fn with_font_widths(mut self, font: &str, widths: FontWidths) -> Self {
+        self.font_widths.insert(font.to_string(), widths);
+        self
+    }
+
+    fn font_widths(&self, font: &str) -> Cow<'_, FontWidths> {
+        match self
+            .font_widths
+            .get(font)
+            .or_else(|| self.font_widths.get(DIALOGUE_FONT))
+        {
+            Some(widths) => Cow::Borrowed(widths),
+            None => Cow::Owned(FontWidths::default()),
+        }
+    }
+
+    /// Measures `text` in columns as the game would display it in `font`, after substitution.
+    /// Compound characters are measured by the glyph they encode to, falling back to the
+    /// widest character of their expansion.
+    pub fn measure_width(&self, text: &str, font: &str) -> Result<usize, text::EncodingError> {
+        let widths = self.font_widths(font);
+        let text = Text(Cow::from(text));
+        let mut width = 0;
+        for ch in text::substitute(&text, self, self.default_fullwidth) {
+            let glyph = self.charset[text::char_index(&ch, self)? as usize];
+            width += match ch {
+                Char::Regular(c) => widths.char_width(c),
+                Char::Compound(s) => widths
+                    .get(glyph)
+                    .unwrap_or_else(|| s.chars().map(|c| widths.char_width(c)).max().unwrap_or(1)),
+            };
+        }
+        Ok(width)
+    }
+
+    /// Breaks `text` into lines no wider than `width` columns of `font`.
+    /// See `layout::wrap_to_width`.
+    pub fn wrap_to_width(&self, text: &str, width: usize, font: &str) -> Vec<String> {
+        layout::wrap_to_width_in(text, width, None, &self.font_widths(font))
+    }
+
+    /// Declares the maximum number of character codes a single string record can hold.
+    /// See `coz::validate_replacements`.
+    pub fn with_max_record_codepoints(self, limit: usize) -> Self {
+        Self {
+            max_record_codepoints: Some(limit),
+            ..self
+        }
+    }
+
+    /// Declares replacements that the game's font prefers for characters it lacks, taking
+    /// precedence over the built-in confusable table. See `GameDef::suggest_replacement`.
+    pub fn with_confusable_overrides(self, overrides: HashMap<char, char>) -> Self {
+        Self {
+            confusable_overrides: overrides,
+            ..self
+        }
+    }
+
+    pub fn max_record_codepoints(&self) -> Option<usize> {
+        self.max_record_codepoints
+    }
+
+    /// Returns the thin space glyph, if the game declares one.
+    pub fn thin_space(&self) -> Option<char> {
+        self.thin_space.and_then(|code| self.glyph_of(code))
+    }
+
+    /// Wraps `text` to `width` columns of the dialogue font (justifying with the thin space
+    /// glyph, if any) and groups the lines into textbox pages of at most `lines_per_page` lines.
+    /// Lines within a page are separated by `[linebreak]`, and every page but the last ends
+    /// with a `[%p]` page break, so that the pages can be concatenated into a single string.
+    pub fn paginate(&self, text: &str, width: usize, lines_per_page: usize) -> Vec<String> {
+        let widths = self.font_widths(DIALOGUE_FONT);
+        let lines = layout::wrap_to_width_in(text, width, self.thin_space(), &widths);
+        let mut pages: Vec<String> = lines
+            .chunks(lines_per_page.max(1))
+            .map(|lines| lines.join("[linebreak]"))
+            .collect();
+        let last = pages.len() - 1;
+        for page in &mut pages[..last] {
+            page.push_str("[%p]");
+        }
+
+        pages
+    }
+
+    /// Measures `text` in columns as the game would display it: after substitution, each
+    /// fullwidth or CJK glyph counts as two columns and every other glyph as one. Compound
+    /// characters take up a single glyph, wide if any character of their expansion is.
+    /// Meant as an approximation for games whose font has no width table.
+    pub fn measure_width_monospace(&self, text: &str) -> Result<u32, text::EncodingError> {
+        let text = Text(Cow::from(text));
+        let mut width = 0;
+        for ch in text::substitute(&text, self, self.default_fullwidth) {
+            text::encode_char(&ch, self)?;
+            width += match ch {
+                Char::Regular(c) => layout::char_width(c),
+                Char::Compound(s) => s.chars().map(layout::char_width).max().unwrap_or(1),
+            } as u32;
+        }
+        Ok(width)
+    }
+
+    /// Estimates how many lines `text` takes up in a textbox `max_px` pixels of the dialogue font
+    /// wide, as wrapped by `GameDef::paginate`. Fails if `text` can't be encoded.
+    pub fn estimate_line_count(
+        &self,
+        text: &str,
+        max_px: u32,
+    ) -> Result<usize, text::EncodingError> {
+        text::encoded_byte_len(&Text(Cow::from(text)), self, self.default_fullwidth)?;
+        Ok(layout::count_lines(
+            text,
+            max_px as usize,
+            &self.font_widths(DIALOGUE_FONT),
+        ))
+    }
+
+    /// Flags the characters of `text` that the charset lacks, but that look like a character
+    /// it has, along with the character that was most likely meant.
+    pub fn find_confusables(&self, text: &str) -> Vec<ConfusableHit> {
+        let encodable = |ch| self.encoding_maps.index_of(ch).is_some();
+        text.char_indices()
+            .filter(|(_, ch)| !encodable(*ch))
+            .filter_map(|(offset, found)| {
+                self.suggest_replacement(found)
+                    .map(|suggestion| ConfusableHit {
+                        offset,
+                        found,
+                        suggestion,
+                    })
+            })
+            .collect()
+    }
+
+    /// Suggests an encodable character that looks like `ch`, preferring the game's
+    /// confusable overrides over the built-in table.
+    pub fn suggest_replacement(&self, ch: char) -> Option<char> {
+        let encodable = |ch| self.encoding_maps.index_of(ch).is_some();
+        self.confusable_overrides
+            .get(&ch)
+            .cloned()
+            .filter(|suggestion| encodable(*suggestion))
+            .or_else(|| {
+                CONFUSABLES
+                    .iter()
+                    .find(|(confusable, suggestion)| *confusable == ch && encodable(*suggestion))
+                    .map(|(_, suggestion)| *suggestion)
+            })
+    }
+
+    /// Returns `text` with the substitutions applied that encoding it with the game's default
+    /// width makes, which shows what will actually be encoded.
+    pub fn apply_substitutions(&self, text: &str) -> String {
+        let text = Text(Cow::from(text));
+        let substituted = text::substitute(&text, self, self.default_fullwidth);
+        Text::from_chars(substituted, true).0.into_owned()
+    }
+
+    /// Encodes `text` into a fixed-length record of `record_codepoints` character codes,
+    /// padding it with `fill`, so that it can be patched in place.
+    pub fn encode_fixed(
+        &self,
+        text: &str,
+        record_codepoints: usize,
+        fill: u16,
+    ) -> Result<Vec<u16>, text::EncodingError> {
+        let text = Text(Cow::from(text));
+        let mut codes = text::encode_str(&text, self, self.default_fullwidth)?;
+        if codes.len() > record_codepoints {
+            return Err(text::EncodingError::RecordOverflow {
+                encoded: codes.len(),
+                capacity: record_codepoints,
+            });
+        }
+        codes.resize(record_codepoints, fill);
+        Ok(codes)
+    }
+
+    /// Encodes `text` like `encode_fixed` without the padding, but only if every character it
+    /// is written with is in `allowed`, for fonts that keep to a subset of the charset. The
+    /// characters are checked as written, before substitution, so compound characters need every
+    /// character of their expansion to be allowed.
+    pub fn encode_restricted(
+        &self,
+        text: &str,
+        allowed: &BTreeSet<char>,
+    ) -> Result<Vec<u16>, text::EncodingError> {
+        let text = Text(Cow::from(text));
+        let disallowed = text
+            .iter(&self.encoding_maps)
+            .flat_map(|ch| match ch {
+                Char::Regular(c) => vec![c],
+                Char::Compound(s) => s.chars().collect(),
+            })
+            .find(|c| !allowed.contains(c));
+        if let Some(c) = disallowed {
+            return Err(text::EncodingError::CharNotAllowed(c));
+        }
+        text::encode_str(&text, self, self.default_fullwidth)
+    }
+
+    /// Whether the game renders Latin text fullwidth by default.
+    pub fn prefers_fullwidth(&self) -> bool {
+        self.default_fullwidth
+    }
+
+    /// Lists the characters that occur in compound character expansions, but have no glyph
+    /// of their own in the charset, so that they can only be written as part of a compound.
+    pub fn compound_only_chars(&self) -> BTreeSet<char> {
+        self.compound_chars
+            .values()
+            .flat_map(|expansion| expansion.chars())
+            .filter(|ch| !self.charset.contains(ch))
+            .collect()
+    }
+
+    /// Groups the compound characters by expansion, keeping only the expansions shared by
+    /// more than one character. Encoding such an expansion always yields the character
+    /// with the lowest character code, so the others do not survive a decode/encode roundtrip.
+    pub fn ambiguous_expansions(&self) -> HashMap<String, Vec<char>> {
+        self.compound_chars
+            .iter()
+            .sorted_by_key(|(ch, _)| **ch)
+            .map(|(ch, expansion)| (expansion.clone(), *ch))
+            .into_group_map()
+            .into_iter()
+            .filter(|(_, chars)| chars.len() > 1)
+            .collect()
+    }
+
+    /// Returns the charset with the padding slots removed, along with a map from the old glyph
+    /// indices to the new ones.
+    pub fn compact_charset(&self) -> (Vec<char>, HashMap<u16, u16>) {
+        let mut compacted = Vec::new();
+        let mut remap = HashMap::new();
+        for (i, ch) in self.charset.iter().enumerate() {
+            if *ch != '\0' {
+                remap.insert(i as u16, compacted.len() as u16);
+                compacted.push(*ch);
+            }
+        }
+        (compacted, remap)
+    }
+}
+
+/// Flags blocklist entries that fullwidth conversion would leave untouched anyway.
+fn check_fullwidth_blocklist(blocklist: &[char]) -> Warnings {
+    let mut warnings = Warnings::new();
+    for ch in blocklist {
+        if text::replace_halfwidth(*ch) == *ch {
+            warnings.push(Warning::NotFullwidthConvertible(*ch));
+        }
+    }
+    warnings
+}
+
+/// Flags compound characters whose expansion holds a Private Use Area character, which would
+/// be taken for another compound character when the expansion is encoded again.
+fn check_compound_expansions(
+    compound_chars: &HashMap<char, String>,
+    reserved_codepoints: Option<&RangeInclusive<char>>,
+    warnings: &mut Warnings,
+) {
+    let pua = reserved_codepoints.unwrap_or(&BMP_PUA);
+    for (ch, expansion) in compound_chars.iter().sorted_by_key(|(ch, _)| **ch) {
+        if let Some(inner) = expansion.chars().find(|c| pua.contains(c)) {
+            warnings.push(Warning::PuaInCompoundExpansion(*ch, inner));
+        }
+    }
+}
+
+/// How the glyphs of a charset are laid out.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CharsetLayout {
+    /// Number of glyphs per bank.
+    pub bank_size: usize,
+    /// Number of index bits per page; glyph `i` of page `p` gets the index `(p << page_shift) | i`.
+    pub page_shift: u32,
+}
+
+impl Default for CharsetLayout {
+    fn default() -> Self {
+        Self {
+            bank_size: DEFAULT_BANK_SIZE,
+            page_shift: DEFAULT_PAGE_SHIFT,
+        }
+    }
+}
+
+/// Splits off the optional header lines of a charset, which declare its layout:
+///
+/// ```text
+/// ;bank=<decimal number>
+/// ;page-shift=<decimal number>
+/// ```
+///
+/// Settings missing from the header take their values from `CharsetLayout::default()`.
+fn parse_charset_header(mut s: &str) -> Result<(CharsetLayout, &str), GameDefError> {
+    let mut layout = CharsetLayout::default();
+    while s.starts_with(';') {
+        let (header, body) = match s.find('\n') {
+            Some(pos) => (&s[..pos], &s[pos + 1..]),
+            None => (s, ""),
+        };
+        let header = header.trim_end_matches('\r');
+        let (key, value) = match header[1..].split_once('=') {
+            Some(setting) => setting,
+            None => break,
+        };
+        match (key, value.parse::<usize>()) {
+            ("bank", Ok(bank_size)) if bank_size > 0 => layout.bank_size = bank_size,
+            ("page-shift", Ok(shift)) if (1..=15).contains(&shift) => {
+                layout.page_shift = shift as u32
+            }
+            _ => return Err(GameDefError::InvalidCharsetHeader(header.to_string())),
+        }
+        s = body;
+    }
+
+    Ok((layout, s))
+}
+
+/// Scans a charset made of one or more pages, separated by `;page` lines.
+/// `first_line` is the source line the charset body starts at.
+fn scan_pages(
+    s: &str,
+    layout: &CharsetLayout,
+    first_line: usize,
+) -> Result<ScannedCharset, GameDefError> {
+    let mut pages = vec![vec![]];
+    for line in s.split('\n') {
+        if line.trim_end_matches('\r') == ";page" {
+            pages.push(vec![]);
+        } else {
+            pages.last_mut().unwrap().push(line);
+        }
+    }
+    if pages.len() == 1 {
+        return Ok(scan_charset(s, layout.bank_size, first_line));
+    }
+
+    let page_size = 1usize << layout.page_shift;
+    if pages.len() * page_size > MAX_GLYPHS {
+        return Err(GameDefError::PageOverflow(pages.len() - 1));
+    }
+
+    let mut charset = ScannedCharset {
+        glyphs: vec![],
+        lines: vec![],
+    };
+    let mut line = first_line;
+    for (i, page) in pages.iter().enumerate() {
+        let mut scanned = scan_charset(&page.join("\n"), layout.bank_size, line);
+        if scanned.glyphs.len() > page_size {
+            return Err(GameDefError::PageOverflow(i));
+        }
+        // Only the very first slot of the charset holds the space
+        if i != 0 && scanned.glyphs.first() == Some(&' ') {
+            scanned.glyphs[0] = '\0';
+            scanned.lines[0] = None;
+        }
+        charset.glyphs.resize(i * page_size, '\0');
+        charset.lines.resize(i * page_size, None);
+        charset.glyphs.extend(scanned.glyphs);
+        charset.lines.extend(scanned.lines);
+        line += page.len() + 1; // skip past the separator
+    }
+
+    Ok(charset)
+}
+
+/// A scanned charset, along with the source line that defines each glyph.
+struct ScannedCharset {
+    glyphs: Vec<char>,
+    lines: Vec<Option<usize>>,
+}
+
+/// Scans a single charset page, whose first line is line `first_line` of the source.
+fn scan_charset(s: &str, bank_size: usize, first_line: usize) -> ScannedCharset {
+    let _charset = s.replace("\r", ""); // Or save the file with LF line endings
+    let _charset: Vec<char> = _charset.chars().collect();
+    let mut charset = Vec::<char>::new();
+    let mut lines = Vec::new();
+    let mut line = first_line;
+    let mut i = 0usize;
+    let mut j = 0usize;
+    while j < _charset.len() {
+        let mut nl = 0usize;
+        while j < _charset.len() && _charset[j] == '\n' {
+            i = (i + bank_size - 1) / bank_size * bank_size; // align upward to the bank size
+            i += nl;
+            j += 1;
+            nl = 1;
+            line += 1;
+        }
+        charset.resize(i + 1, '\0');
+        lines.resize(i + 1, None);
+        if j < _charset.len() {
+            charset[i] = _charset[j];
+            lines[i] = Some(line);
+            if i != 0 && charset[i] == ' ' {
+                charset[i] = '\0';
+                lines[i] = None;
+            }
+        }
+        i += 1;
+        j += 1;
+    }
+    ScannedCharset {
+        glyphs: charset,
+        lines,
+    }
+}
+
+/// Produces `count` pairs of text and the character codes it encodes to, for checking other
+/// implementations of the encoder against this one. Each text strings together up to eight
+/// characters of the charset and compound characters (written in brackets), picked by a
+/// fixed-seed generator, so the same definition always yields the same vectors.
+pub fn generate_test_vectors(game: &GameDef, count: usize) -> Vec<(String, Vec<u16>)> {
+    let encode =
+        |s: &str| text::encode_str(&Text(Cow::from(s)), game, game.prefers_fullwidth()).ok();
+    let regular = game
+        .encoding_maps
+        .forward_table()
+        .into_iter()
+        .map(|(ch, _)| ch)
+        .filter(|ch| !ch.is_control() && *ch != '[' && *ch != ']')
+        .filter(|ch| !game.compound_chars.contains_key(ch))
+        .map(|ch| ch.to_string());
+    let compound = game
+        .compound_chars
+        .values()
+        .sorted()
+        .dedup()
+        .map(|expansion| format!("[{}]", expansion));
+    let units: Vec<_> = regular
+        .chain(compound)
+        .filter(|unit| encode(unit).is_some())
+        .collect();
+    if units.is_empty() {
+        return Vec::new();
+    }
+
+    // xorshift64, which is all the randomness picking samples needs
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as usize
+    };
+    (0..count)
+        .map(|_| {
+            let len = next() % 8 + 1;
+            let input: String = (0..len)
+                .map(|_| units[next() % units.len()].as_str())
+                .collect();
+            let codes = encode(&input).unwrap();
+            (input, codes)
+        })
+        .collect()
+}
+
+/// Writes `glyphs` out as the text of a `charset.utf8` file in the default layout, which scans
+/// back to the same indices: one line per bank, with padding slots (`'\0'`) written as spaces.
+/// Only the first slot can hold a space glyph.
+pub fn build_charset(glyphs: &[char]) -> String {
+    glyphs
+        .chunks(DEFAULT_BANK_SIZE)
+        .map(|bank| {
+            let line: String = bank
+                .iter()
+                .map(|ch| if *ch == '\0' { ' ' } else { *ch })
+                .collect();
+            // The line break pads the rest of the bank, but a bank cannot be left empty
+            match line.trim_end_matches(' ') {
+                "" => " ".to_string(),
+                trimmed => trimmed.to_string(),
+            }
+        })
+        .join("\n")
+}
+
+pub fn get(game: Game) -> &'static GameDef {
+    DEFS.iter().find(|x| x.game == game).unwrap()
+}
+
+/// Returns the aliases of a built-in game, the primary one first. Custom games have no
+/// built-in aliases.
+pub fn aliases_for(game: Game) -> &'static [&'static str] {
+    BUILTIN_GAMES
+        .iter()
+        .find(|x| x.game == game)
+        .map_or(&[], |x| x.aliases)
+}
+
+pub fn get_by_alias(alias: &str) -> Option<&'static GameDef> {
+    debug_assert!(DEFS
+        .iter()
+        .flat_map(|x| x.aliases.iter())
+        .all(|x| is_valid_alias(x)));
+    DEFS.iter().find(|x| x.aliases.iter().any(|x| x == alias))
+}
+
+/// Aliases are matched verbatim on the command line, so they are restricted to
+/// lowercase ASCII letters and digits.
+fn is_valid_alias(alias: &str) -> bool {
+    !alias.is_empty()
+        && alias
+            .chars()
+            .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit())
+}
+
+#[derive(Eq, PartialEq, Debug)]
+struct PuaMapping<'a> {
+    codepoint_range: RangeInclusive<char>,
+    ch: &'a str,
+}
+
+impl<'a> PuaMapping<'a> {
+    /// The number of codepoints the mapping covers, not minding the surrogates a range may span.
+    fn len(&self) -> usize {
+        let (start, end) = (*self.codepoint_range.start(), *self.codepoint_range.end());
+        (end as usize + 1).saturating_sub(start as usize)
+    }
+
+    fn new(codepoint_range: RangeInclusive<char>, ch: &'a str) -> Self {
+        Self {
+            codepoint_range,
+            ch,
+        }
+    }
+
+    pub fn parse(i: &str) -> IResult<&str, PuaMapping> {
+        fn codepoint(i: &str) -> IResult<&str, char> {
+            map_opt(
+                map_res(is_not("-]"), |s| u32::from_str_radix(s, 16)),
+                std::char::from_u32,
+            )(i)
+        }
+
+        fn range(i: &str) -> IResult<&str, RangeInclusive<char>> {
+            map(
+                delimited(
+                    char('['),
+                    pair(codepoint, opt(preceded(char('-'), codepoint))),
+                    char(']'),
+                ),
+                |(a, b)| match (a, b) {
+                    (a, Some(b)) => (a..=b),
+                    _ => (a..=a),
+                },
+            )(i)
+        }
+
+        // A ";;" after whitespace starts a comment running to the end of the line, and the
+        // whitespace goes with it. The first character always belongs to the expansion, so a
+        // mapping to a lone space can still carry a comment.
+        fn value(i: &str) -> IResult<&str, &str> {
+            map(not_line_ending, |v: &str| {
+                let first = v.chars().next().map_or(0, char::len_utf8);
+                let comment = v[first..]
+                    .match_indices(";;")
+                    .map(|(pos, _)| first + pos)
+                    .find(|&pos| v[..pos].ends_with(char::is_whitespace));
+                match comment {
+                    Some(pos) => &v[..first + v[first..pos].trim_end().len()],
+                    None => v,
+                }
+            })(i)
+        }
+
+        let range = verify(range, |r: &RangeInclusive<char>| r.start() <= r.end());
+        map(tuple((range, char('='), value)), |(r, _, ch)| {
+            PuaMapping::new(r, ch)
+        })(i)
+    }
+}
+
+#[cfg(test)]
+fn parse_compound_ch_map(i: &str) -> Result<HashMap<char, String>, CompoundMapError> {
+    parse_compound_ch_map_limited(i, DEFAULT_MAX_COMPOUND_MAPPINGS)
+}
+
+/// Like `parse_compound_ch_map`, but fails with `CompoundMapError::TooManyMappings` once the
+/// map would hold more than `max_mappings` mappings, before expanding the offending range.
+fn parse_compound_ch_map_limited(
+    i: &str,
+    max_mappings: usize,
+) -> Result<HashMap<char, String>, CompoundMapError> {
+    let mut map = HashMap::new();
+    for (line_no, line) in i.lines().enumerate().filter(|(_, l)| !l.is_empty()) {
+        let (_, m) = all_consuming(PuaMapping::parse)(line)
+            .map_err(|_| CompoundMapError::Malformed(line_no + 1))?;
+        if map.len() + m.len() > max_mappings {
+            return Err(CompoundMapError::TooManyMappings(max_mappings));
+        }
+        for codepoint in m.codepoint_range {
+            map.insert(codepoint, m.ch.to_string());
+        }
+    }
+
+    resolve_references(&map)
+}
+
+/// Parses a compound map value of the form `[XXXX]`, which stands for the expansion
+/// of another codepoint.
+fn parse_reference(value: &str) -> Option<char> {
+    let hex = value.strip_prefix('[')?.strip_suffix(']')?;
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(std::char::from_u32)
+}
+
+/// Replaces references to other codepoints with their (transitively resolved) expansions.
+fn resolve_references(
+    map: &HashMap<char, String>,
+) -> Result<HashMap<char, String>, CompoundMapError> {
+    let mut resolved = HashMap::with_capacity(map.len());
+    for (codepoint, mut value) in map.iter().sorted_by_key(|(codepoint, _)| **codepoint) {
+        let mut visited = vec![*codepoint];
+        while let Some(target) = parse_reference(value) {
+            if visited.contains(&target) {
+                return Err(CompoundMapError::CyclicReference(target));
+            }
+            visited.push(target);
+            value = map
+                .get(&target)
+                .ok_or(CompoundMapError::UndefinedReference(target))?;
+        }
+        resolved.insert(*codepoint, value.clone());
+    }
+
+    Ok(resolved)
+}
+
+/// Merges two compound character maps, with `overlay` contributing entries missing from `base`.
+/// Fails on the first (lowest) codepoint that the two maps expand differently.
+pub fn merge_compound_maps(
+    base: &HashMap<char, String>,
+    overlay: &HashMap<char, String>,
+) -> Result<HashMap<char, String>, MergeConflict> {
+    let mut merged = base.clone();
+    for (codepoint, expansion) in overlay.iter().sorted_by_key(|(codepoint, _)| **codepoint) {
+        match merged.get(codepoint) {
+            Some(existing) if existing != expansion => {
+                return Err(MergeConflict {
+                    codepoint: *codepoint,
+                    base: existing.clone(),
+                    overlay: expansion.clone(),
+                })
+            }
+            Some(_) => {}
+            None => {
+                merged.insert(*codepoint, expansion.clone());
+            }
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Lists, in ascending order, the keys of a compound character map that fall outside the PUA
+/// slots available in a target font.
+pub fn compound_map_fits(
+    map: &HashMap<char, String>,
+    available_pua: &RangeInclusive<char>,
+) -> Vec<char> {
+    map.keys()
+        .filter(|codepoint| !available_pua.contains(codepoint))
+        .cloned()
+        .sorted()
+        .collect()
+}
+
+/// Checks a manifest for problems without building its encoding maps.
+/// Unlike `GameDef` construction, this reports every problem rather than just the first.
+pub fn validate_manifest(manifest: &GameDefManifest) -> Vec<ManifestIssue> {
+    let mut issues = Vec::new();
+    let charset = match parse_charset_header(&manifest.charset) {
+        Ok((layout, charset)) => match scan_pages(charset, &layout, 1) {
+            Ok(scanned) => scanned.glyphs,
+            Err(err) => {
+                if let GameDefError::PageOverflow(page) = err {
+                    issues.push(ManifestIssue::PageOverflow(page));
+                }
+                vec![]
+            }
+        },
+        Err(err) => {
+            if let GameDefError::InvalidCharsetHeader(header) = err {
+                issues.push(ManifestIssue::InvalidCharsetHeader(header));
+            }
+            vec![]
+        }
+    };
+    let glyphs: Vec<_> = charset
+        .iter()
+        .filter(|ch| **ch != '\0' && **ch != ' ')
+        .collect();
+    if glyphs.is_empty() {
+        issues.push(ManifestIssue::EmptyCharset);
+    }
+    match charset.first() {
+        Some(ch) if !NULL_GLYPHS.contains(ch) => issues.push(ManifestIssue::InvalidNullGlyph(*ch)),
+        _ => {}
+    }
+    issues.extend(
+        glyphs
+            .iter()
+            .duplicates()
+            .sorted()
+            .map(|ch| ManifestIssue::DuplicateGlyph(**ch)),
+    );
+
+    if let Some(reserved) = &manifest.reserved_codepoints {
+        if !BMP_PUA.contains(reserved.start()) || !BMP_PUA.contains(reserved.end()) {
+            issues.push(ManifestIssue::ReservedOutsidePua(reserved.clone()));
+        }
+    }
+
+    let mut map = HashMap::new();
+    let lines = manifest.compound_chars.lines().enumerate();
+    for (line_no, line) in lines.filter(|(_, l)| !l.is_empty()) {
+        match all_consuming(PuaMapping::parse)(line) {
+            Ok((_, m)) => {
+                for codepoint in m.codepoint_range {
+                    map.insert(codepoint, m.ch.to_string());
+                }
+            }
+            Err(_) => issues.push(ManifestIssue::CompoundMap(CompoundMapError::Malformed(
+                line_no + 1,
+            ))),
+        }
+    }
+    if let Err(err) = resolve_references(&map) {
+        issues.push(ManifestIssue::CompoundMap(err));
+    }
+
+    for codepoint in map.keys().sorted() {
+        if !charset.contains(codepoint) {
+            issues.push(ManifestIssue::UnresolvedPuaChar(*codepoint));
+        }
+    }
+    if let Some(reserved) = &manifest.reserved_codepoints {
+        issues.extend(
+            charset
+                .iter()
+                .filter(|ch| reserved.contains(ch) && !map.contains_key(ch))
+                .sorted()
+                .dedup()
+                .map(|ch| ManifestIssue::ReservedOverlap(*ch)),
+        );
+    }
+
+    issues
+}
+
+/// Constructs every built-in game from its embedded resources and checks that its data is
+/// internally consistent, returning the problems found along with the primary alias of the
+/// game. Charsets of the games hold the same glyph in several slots, so duplicate glyphs are
+/// not reported.
+pub fn self_check() -> Vec<(&'static str, SelfCheckIssue)> {
+    let mut issues = Vec::new();
+    for builtin in BUILTIN_GAMES {
+        let alias = builtin.aliases[0];
+        let def = match builtin.try_build() {
+            Ok(def) => def,
+            Err(err) => {
+                issues.push((alias, SelfCheckIssue::Construction(err)));
+                continue;
+            }
+        };
+
+        // Construction succeeding means both resources exist and are valid UTF-8
+        let resource = |name| {
+            let path = format!("{}/{}", builtin.resource_dir, name);
+            String::from_utf8_lossy(&ResourceDir::get(&path).unwrap()).into_owned()
+        };
+        let manifest = GameDefManifest {
+            full_name: builtin.full_name.to_string(),
+            aliases: builtin.aliases.iter().map(|x| x.to_string()).collect(),
+            reserved_codepoints: builtin.reserved_codepoints.clone(),
+            charset: resource("charset.utf8"),
+            compound_chars: resource("compound_chars.map"),
+        };
+        issues.extend(
+            validate_manifest(&manifest)
+                .into_iter()
+                .filter(|issue| !matches!(issue, ManifestIssue::DuplicateGlyph(_)))
+                .map(|issue| (alias, SelfCheckIssue::Manifest(issue))),
+        );
+        issues.extend(
+            def.warnings()
+                .iter()
+                .map(|warning| (alias, SelfCheckIssue::Warning(warning.clone()))),
+        );
+        issues.extend(
+            def.verify_roundtrip()
+                .into_iter()
+                .map(|code| (alias, SelfCheckIssue::Roundtrip(code))),
+        );
+    }
+
+    issues
+}
+
+impl From<CompoundMapError> for GameDefError {
+    fn from(err: CompoundMapError) -> Self {
+        GameDefError::CompoundMap(err)
+    }
+}
+
+impl fmt::Display for GameDefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameDefError::MissingResource(path) => write!(f, "missing resource: '{}'", path),
+            GameDefError::InvalidUtf8(name) => write!(f, "{} is not valid UTF-8", name),
+            GameDefError::InvalidCharsetHeader(header) => {
+                write!(f, "invalid charset header: '{}'", header)
+            }
+            GameDefError::EmptyCharset => write!(f, "the charset does not contain any characters"),
+            GameDefError::PageOverflow(page) => {
+                write!(f, "charset page {} does not fit into the code space", page)
+            }
+            GameDefError::CompoundMap(err) => fmt::Display::fmt(err, f),
+            GameDefError::MissingPuaChars(chars) => write!(
+                f,
+                "the following Private Use Area characters were not found in the charset: [{}]",
+                chars
+                    .iter()
+                    .map(|ch| format!("'{}'", ch.escape_unicode()))
+                    .join(", ")
+            ),
+            GameDefError::InvalidAlias(name) => write!(
+                f,
+                "'{}' is not a valid game alias, use lowercase ASCII letters and digits only",
+                name
+            ),
+            GameDefError::InvalidNullGlyph(ch) => write!(
+                f,
+                "the first charset slot is reserved for a space or an empty glyph, found '{}'",
+                ch.escape_unicode()
+            ),
+        }
+    }
+}
+
+impl fmt::Display for CompoundMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompoundMapError::Malformed(line) => {
+                write!(f, "malformed compound character mapping at line {}", line)
+            }
+            CompoundMapError::CyclicReference(ch) => write!(
+                f,
+                "cyclic reference involving '{}' in the compound character map",
+                ch.escape_unicode()
+            ),
+            CompoundMapError::UndefinedReference(ch) => write!(
+                f,
+                "reference to '{}', which is not defined in the compound character map",
+                ch.escape_unicode()
+            ),
+            CompoundMapError::TooManyMappings(limit) => write!(
+                f,
+                "the compound character map holds more than {} mappings",
+                limit
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ManifestIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestIssue::InvalidCharsetHeader(header) => {
+                write!(f, "invalid charset header: '{}'", header)
+            }
+            ManifestIssue::EmptyCharset => write!(f, "the charset does not contain any characters"),
+            ManifestIssue::PageOverflow(page) => {
+                write!(f, "charset page {} does not fit into the code space", page)
+            }
+            ManifestIssue::InvalidNullGlyph(ch) => write!(
+                f,
+                "the first charset slot is reserved for a space or an empty glyph, found '{}'",
+                ch.escape_unicode()
+            ),
+            ManifestIssue::DuplicateGlyph(ch) => {
+                write!(f, "'{}' appears in the charset more than once", ch)
+            }
+            ManifestIssue::CompoundMap(err) => fmt::Display::fmt(err, f),
+            ManifestIssue::UnresolvedPuaChar(ch) => write!(
+                f,
+                "'{}' is mapped in the compound character map, but not found in the charset",
+                ch.escape_unicode()
+            ),
+            ManifestIssue::ReservedOverlap(ch) => write!(
+                f,
+                "'{}' lies in the reserved range, but is an ordinary glyph of the charset",
+                ch.escape_unicode()
+            ),
+            ManifestIssue::ReservedOutsidePua(range) => write!(
+                f,
+                "the reserved range '{}'..'{}' lies outside the Private Use Area",
+                range.start().escape_unicode(),
+                range.end().escape_unicode()
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SelfCheckIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelfCheckIssue::Construction(err) => fmt::Display::fmt(err, f),
+            SelfCheckIssue::Manifest(issue) => fmt::Display::fmt(issue, f),
+            SelfCheckIssue::Warning(warning) => fmt::Display::fmt(warning, f),
+            SelfCheckIssue::Roundtrip(code) => write!(
+                f,
+                "the glyph with character code {:04X} does not decode back to itself",
+                code
+            ),
+        }
+    }
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "conflicting mappings for '{}': '{}' (base) and '{}' (overlay)",
+            self.codepoint.escape_unicode(),
+            self.base,
+            self.overlay
+        )
+    }
+}
+
+/// Builds a throwaway definition from a charset and compound character map, for tests.
+#[cfg(test)]
+pub(crate) fn test_def(charset: &str, compound_chars: &str) -> GameDef {
+    build_test_def(charset, compound_chars, None)
+}
+
+/// Like `test_def`, with the given range of reserved codepoints.
+#[cfg(test)]
+pub(crate) fn test_def_reserved(
+    charset: &str,
+    compound_chars: &str,
+    reserved: RangeInclusive<char>,
+) -> GameDef {
+    build_test_def(charset, compound_chars, Some(reserved))
+}
+
+#[cfg(test)]
+fn build_test_def(
+    charset: &str,
+    compound_chars: &str,
+    reserved: Option<RangeInclusive<char>>,
+) -> GameDef {
+    GameDef::from_parts(
+        Game::SteinsGate0,
+        "Steins;Gate 0",
+        &["sg0"],
+        reserved,
+        vec![],
+        charset.as_bytes(),
+        compound_chars.as_bytes(),
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pua_mapping() {
+        assert_eq!(
+            PuaMapping::parse("[E01C]=meow").unwrap().1,
+            PuaMapping::new('\u{E01C}'..='\u{E01C}', "meow")
+        );
+
+        assert_eq!(
+            PuaMapping::parse("[E01C-E01F]=¹⁸").unwrap().1,
+            PuaMapping::new('\u{E01C}'..='\u{E01F}', "¹⁸")
+        );
+    }
+
+    #[test]
+    fn parse_pua_mapping_comment() {
+        assert_eq!(
+            PuaMapping::parse("[E01C]=¹⁸  ;; superscript 18").unwrap().1,
+            PuaMapping::new('\u{E01C}'..='\u{E01C}', "¹⁸")
+        );
+        assert_eq!(
+            PuaMapping::parse("[E01C]=a;b").unwrap().1,
+            PuaMapping::new('\u{E01C}'..='\u{E01C}', "a;b")
+        );
+        assert_eq!(
+            PuaMapping::parse("[E01C]=;) ;; winking face").unwrap().1,
+            PuaMapping::new('\u{E01C}'..='\u{E01C}', ";)")
+        );
+        assert_eq!(
+            PuaMapping::parse("[E000]= ;; pad").unwrap().1,
+            PuaMapping::new('\u{E000}'..='\u{E000}', " ")
+        );
+        assert_eq!(
+            PuaMapping::parse("[E01C]=a;;b").unwrap().1,
+            PuaMapping::new('\u{E01C}'..='\u{E01C}', "a;;b")
+        );
+        assert_eq!(
+            PuaMapping::parse("[E01C]=;; ;; two semicolons").unwrap().1,
+            PuaMapping::new('\u{E01C}'..='\u{E01C}', ";;")
+        );
+    }
+
+    #[test]
+    fn compound_map_references() {
+        let map = parse_compound_ch_map("[E01C]=¹⁸\n[E040]=[E041]\n[E041]=[E01C]").unwrap();
+        assert_eq!(map[&'\u{E040}'], "¹⁸");
+        assert_eq!(map[&'\u{E041}'], "¹⁸");
+
+        assert_eq!(
+            parse_compound_ch_map("[E01C]=[E040]\n[E040]=[E041]\n[E041]=[E040]"),
+            Err(CompoundMapError::CyclicReference('\u{E040}'))
+        );
+        assert_eq!(
+            parse_compound_ch_map("[E01C]=[E01C]"),
+            Err(CompoundMapError::CyclicReference('\u{E01C}'))
+        );
+        assert_eq!(
+            parse_compound_ch_map("[E01C]=[E040]"),
+            Err(CompoundMapError::UndefinedReference('\u{E040}'))
+        );
+    }
+
+    #[test]
+    fn compound_map_mapping_limit() {
+        let map = "[E000-E00F]=¹⁸\n[E010]=ü";
+        assert_eq!(parse_compound_ch_map_limited(map, 17).unwrap().len(), 17);
+        assert_eq!(
+            parse_compound_ch_map_limited(map, 16),
+            Err(CompoundMapError::TooManyMappings(16))
+        );
+        assert_eq!(
+            parse_compound_ch_map("[0000-10FFFF]=x"),
+            Err(CompoundMapError::TooManyMappings(
+                DEFAULT_MAX_COMPOUND_MAPPINGS
+            ))
+        );
+    }
+
+    #[test]
+    fn merge_compound_maps_disjoint() {
+        let base = parse_compound_ch_map("[E01C]=¹⁸").unwrap();
+        let overlay = parse_compound_ch_map("[E01C]=¹⁸\n[E01D]=ü").unwrap();
+        let merged = merge_compound_maps(&base, &overlay).unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[&'\u{E01D}'], "ü");
+    }
+
+    #[test]
+    fn merge_compound_maps_conflict() {
+        let base = parse_compound_ch_map("[E01C]=¹⁸\n[E01D]=ü").unwrap();
+        let overlay = parse_compound_ch_map("[E01D]=ë").unwrap();
+        assert_eq!(
+            merge_compound_maps(&base, &overlay),
+            Err(MergeConflict {
+                codepoint: '\u{E01D}',
+                base: "ü".to_string(),
+                overlay: "ë".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn compound_map_outside_pua_range() {
+        let map = parse_compound_ch_map("[E01C]=¹⁸\n[E01D]=ü\n[E100]=ë").unwrap();
+        assert_eq!(
+            compound_map_fits(&map, &('\u{E000}'..='\u{E0FF}')),
+            vec!['\u{E100}']
+        );
+        assert!(compound_map_fits(&map, &('\u{E000}'..='\u{E100}')).is_empty());
+    }
+
+    #[test]
+    fn malformed_resources_do_not_panic() {
+        let charset = ResourceDir::get("sg0/charset.utf8").unwrap();
+        let compound_chars = ResourceDir::get("sg0/compound_chars.map").unwrap();
+        let from_parts = |charset: &[u8], compound_chars: &[u8]| {
+            GameDef::from_parts(
+                Game::SteinsGate0,
+                "Steins;Gate 0",
+                &["sg0"],
+                None,
+                vec![],
+                charset,
+                compound_chars,
+            )
+        };
+        assert!(from_parts(&charset, &compound_chars).is_ok());
+
+        let bad_charsets: &[&[u8]] = &[
+            &charset[..charset.len() - 1], // truncated in the middle of a multibyte char
+            &[0xFF, 0xFE, 0x00],
+            b"",
+            &charset[..256],
+        ];
+        for bad in bad_charsets {
+            assert!(from_parts(bad, &compound_chars).is_err());
+        }
+
+        let bad_maps: &[&[u8]] = &[
+            &[b'[', 0xC3, b']', b'=', b'x'],
+            b"[E01F-E01C]=x",
+            b"[E01C=x",
+            b"[D800]=x",
+            b"[ZZZZ]=x",
+            b"meow",
+            b"[E01C]=x\n[E01D]",
+        ];
+        for bad in bad_maps {
+            assert!(from_parts(&charset, bad).is_err());
+        }
+    }
+
+    #[test]
+    fn builtin_source() {
+        for alias in &["sg0", "sg0zhs", "rnd"] {
+            let def = get_by_alias(alias).unwrap();
+            assert_eq!(def.source(), &GameDefSource::Embedded(alias));
+        }
+    }
+
+    #[test]
+    fn aliases_are_lowercase_alphanumeric() {
+        for alias in DEFS.iter().flat_map(|x| x.aliases.iter()) {
+            assert!(is_valid_alias(alias), "invalid alias: '{}'", alias);
+        }
+        assert!(!is_valid_alias("Sg0"));
+        assert!(!is_valid_alias("sg 0"));
+        assert!(!is_valid_alias(""));
+    }
+
+    #[test]
+    fn default_fullwidth() {
+        for (alias, expected) in &[
+            ("sghd", false),
+            ("sghdzhs", true),
+            ("rn", false),
+            ("sglbp", false),
+            ("sg0", false),
+            ("sg0zhs", true),
+            ("rnd", false),
+        ] {
+            assert_eq!(
+                get_by_alias(alias).unwrap().prefers_fullwidth(),
+                *expected,
+                "{}",
+                alias
+            );
+        }
+    }
+
+    #[test]
+    fn empty_charset() {
+        for charset in &["", "\n\n", " \n   "] {
+            let res = GameDef::from_parts(
+                Game::SteinsGate0,
+                "Steins;Gate 0",
+                &["sg0"],
+                None,
+                vec![],
+                charset.as_bytes(),
+                b"[E01C]=x",
+            );
+            assert!(matches!(res, Err(GameDefError::EmptyCharset)));
+        }
+    }
+
+    #[test]
+    fn charset_bank_size_header() {
+        assert_eq!(
+            parse_charset_header(" ab").unwrap(),
+            (CharsetLayout::default(), " ab")
+        );
+        assert_eq!(
+            parse_charset_header(";bank=128\r\n ab").unwrap(),
+            (
+                CharsetLayout {
+                    bank_size: 128,
+                    ..CharsetLayout::default()
+                },
+                " ab"
+            )
+        );
+        assert!(parse_charset_header(";bank=0\n ab").is_err());
+        assert!(parse_charset_header(";bank=meow\n ab").is_err());
+
+        let default = scan_charset(" ab\ncd", DEFAULT_BANK_SIZE, 1).glyphs;
+        assert_eq!(default.len(), 66);
+        assert_eq!(default[64], 'c');
+
+        let (layout, body) = parse_charset_header(";bank=128\n ab\ncd").unwrap();
+        let wide = scan_charset(body, layout.bank_size, 1).glyphs;
+        assert_eq!(wide.len(), 130);
+        assert_eq!(wide[128], 'c');
+        assert_eq!(wide[64], '\0');
+    }
+
+    #[test]
+    fn rebuild_charset() {
+        let glyphs = &get_by_alias("sg0zhs").unwrap().charset;
+        let rebuilt = build_charset(glyphs);
+        assert_eq!(&scan_charset(&rebuilt, DEFAULT_BANK_SIZE, 1).glyphs, glyphs);
+
+        // Padding at the end of a bank, and a bank of padding only
+        let mut glyphs = vec!['a'; 3 * DEFAULT_BANK_SIZE + 1];
+        glyphs[0] = ' ';
+        glyphs[DEFAULT_BANK_SIZE - 1] = '\0';
+        glyphs[DEFAULT_BANK_SIZE..2 * DEFAULT_BANK_SIZE].fill('\0');
+        let rebuilt = build_charset(&glyphs);
+        assert_eq!(rebuilt.lines().nth(1), Some(" "));
+        assert_eq!(scan_charset(&rebuilt, DEFAULT_BANK_SIZE, 1).glyphs, glyphs);
+    }
+
+    #[test]
+    fn game_of_alias() {
+        assert_eq!(get_by_alias("sg0").unwrap().game(), Game::SteinsGate0);
+        assert_eq!(get_by_alias("sg0zhs").unwrap().game(), Game::SteinsGate0Zhs);
+        assert_eq!(
+            get_by_alias("sghdzhs").unwrap().game(),
+            Game::SteinsGateHDZhs
+        );
+        for def in DEFS.iter() {
+            assert!(std::ptr::eq(get(def.game()), def), "{}", def.full_name);
+        }
+    }
+
+    #[test]
+    fn embedded_resources_are_consistent() {
+        let issues = self_check();
+        assert!(issues.is_empty(), "{:?}", issues);
+        for def in DEFS.iter() {
+            assert!(def.verify_roundtrip().is_empty(), "{}", def.full_name);
+        }
+    }
+
+    #[test]
+    fn duplicate_glyphs_roundtrip() {
+        let def = test_def(" aba", "");
+        assert_eq!(
+            text::encode_char(&Char::Regular('a'), &def).unwrap(),
+            0x8003
+        );
+        assert!(def.verify_roundtrip().is_empty());
+    }
+
+    #[test]
+    fn test_vectors_roundtrip() {
+        let sg0 = get_by_alias("sg0").unwrap();
+        let vectors = generate_test_vectors(sg0, 200);
+        assert_eq!(vectors.len(), 200);
+        assert_eq!(vectors, generate_test_vectors(sg0, 200));
+        assert!(vectors.iter().any(|(input, _)| input.contains('[')));
+        // The charset's unmapped PUA glyphs are fair game for the encoder
+        let options = text::DecodeOptions {
+            literal_unmapped_pua: true,
+            ..text::DecodeOptions::default()
+        };
+        for (input, codes) in &vectors {
+            let text = Text(Cow::from(input.as_str()));
+            assert_eq!(&text::encode_str(&text, sg0, false).unwrap(), codes);
+            let mut decoded = String::new();
+            text::decode_into_with(codes, sg0, true, &options, &mut decoded).unwrap();
+            let decoded = Text(Cow::from(decoded));
+            assert_eq!(&text::encode_str(&decoded, sg0, false).unwrap(), codes);
+        }
+    }
+
+    #[test]
+    fn classify_pua_chars() {
+        let def = test_def_reserved(" a\u{E000}\u{E001}", "[E001]=ab", '\u{E100}'..='\u{E1FF}');
+        assert_eq!(def.pua_kind('\u{E000}'), Some(PuaKind::LiteralGlyph));
+        assert_eq!(def.pua_kind('\u{E001}'), Some(PuaKind::CompoundExpansion));
+        assert_eq!(def.pua_kind('\u{E150}'), Some(PuaKind::EngineReserved));
+        assert_eq!(def.pua_kind('\u{E300}'), None);
+        assert_eq!(def.pua_kind('a'), None);
+    }
+
+    #[test]
+    fn aliases_of_game() {
+        assert_eq!(aliases_for(Game::SteinsGate0), &["sg0", "steinsgate0"]);
+        assert!(!aliases_for(Game::SteinsGate0).contains(&"sg0zhs"));
+        assert_eq!(aliases_for(Game::SteinsGate0Zhs)[0], "sg0zhs");
+        assert!(aliases_for(Game::Custom).is_empty());
+        for def in DEFS.iter() {
+            let primary = aliases_for(def.game())[0];
+            assert_eq!(get_by_alias(primary).unwrap().game(), def.game());
+        }
+    }
+
+    #[test]
+    fn fullwidth_blocklist_warnings() {
+        for def in DEFS.iter() {
+            assert!(def.warnings().is_empty(), "{}", def.full_name);
+        }
+
+        let def = GameDef::from_parts(
+            Game::SteinsGate0,
+            "Steins;Gate 0",
+            &["sg0"],
+            None,
+            vec!['-', 'あ', '['],
+            " ab".as_bytes(),
+            b"",
+        )
+        .unwrap();
+        let warnings: Vec<_> = def.warnings().iter().collect();
+        assert_eq!(warnings, vec![&Warning::NotFullwidthConvertible('あ')]);
+    }
+
+    #[test]
+    fn pua_in_compound_expansion() {
+        let def = test_def(" ab\u{E01C}\u{E01D}", "[E01C]=ab\n[E01D]=a\u{E01C}");
+        let warnings: Vec<_> = def.warnings().iter().collect();
+        assert_eq!(
+            warnings,
+            vec![&Warning::PuaInCompoundExpansion('\u{E01D}', '\u{E01C}')]
+        );
+    }
+
+    #[test]
+    fn display_char_of_codepoint() {
+        let def = get_by_alias("sg0").unwrap();
+        let a = text::encode_char(&Char::Regular('A'), def).unwrap();
+        assert_eq!(def.codepoint_to_display_char(a), Some('A'));
+
+        let compound = text::encode_char(&Char::Compound("ü"), def).unwrap();
+        assert_eq!(def.codepoint_to_display_char(compound), None);
+        let past_end = 0x8000 + def.charset().len() as u16;
+        assert_eq!(def.codepoint_to_display_char(past_end), None);
+    }
+
+    #[test]
+    fn fixed_length_records() {
+        let def = get_by_alias("sg0").unwrap();
+        let space = text::encode_char(&Char::Regular(' '), def).unwrap();
+        let encoded = text::encode_str(&Text(Cow::from("Tuturu")), def, false).unwrap();
+
+        assert_eq!(def.encode_fixed("Tuturu", 6, space).unwrap(), encoded);
+        let padded = def.encode_fixed("Tuturu", 8, space).unwrap();
+        assert_eq!(padded[..6], encoded[..]);
+        assert_eq!(padded[6..], [space, space]);
+        assert_eq!(
+            def.encode_fixed("Tuturu", 5, space),
+            Err(text::EncodingError::RecordOverflow {
+                encoded: 6,
+                capacity: 5
+            })
+        );
+    }
+
+    #[test]
+    fn glyph_index_distance() {
+        let def = get_by_alias("sg0").unwrap();
+        let a = def.charset()[0x100];
+        let b = def.charset()[0x101];
+        assert_eq!(def.index_distance(a, b), Some(1));
+        assert_eq!(def.index_distance(b, a), Some(-1));
+        assert_eq!(def.index_distance(a, a), Some(0));
+        assert_eq!(def.index_distance(a, '\u{1F600}'), None);
+    }
+
+    #[test]
+    fn null_glyph_slot() {
+        for def in DEFS.iter() {
+            assert_eq!(def.null_glyph(), ' ', "{}", def.full_name);
+        }
+        let from_parts = |charset: &str| {
+            GameDef::from_parts(
+                Game::SteinsGate0,
+                "Steins;Gate 0",
+                &["sg0"],
+                None,
+                vec![],
+                charset.as_bytes(),
+                b"",
+            )
+        };
+        assert_eq!(from_parts("\0ab").unwrap().null_glyph(), '\0');
+        assert!(matches!(
+            from_parts("ab"),
+            Err(GameDefError::InvalidNullGlyph('a'))
+        ));
+    }
+
+    #[test]
+    fn padding_slots() {
+        let def = test_def(" ab c", "");
+        assert!(!def.is_padding(0x8001));
+        // Spaces past the first slot are padding
+        assert!(def.is_padding(0x8003));
+        assert!(!def.is_padding(0x8004));
+        assert!(def.is_padding(0x8005));
+    }
+
+    #[test]
+    fn thin_space_glyph() {
+        let def = test_def(" ab\u{E100}", "");
+        assert_eq!(def.thin_space(), None);
+        let def = def.with_thin_space(0x8003);
+        assert_eq!(def.thin_space(), Some('\u{E100}'));
+        assert_eq!(
+            crate::layout::wrap_to_width_with("a b ab", 4, def.thin_space()),
+            vec!["a \u{E100}b", "ab"]
+        );
+    }
+
+    #[test]
+    fn paginate_long_line() {
+        let def = get_by_alias("sg0").unwrap();
+        let text = "I am the mad scientist Hououin Kyouma and this world is mine to rule";
+        // Eight lines of up to 10 columns
+        let pages = def.paginate(text, 10, 3);
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[0], "I am the[linebreak]mad[linebreak]scientist[%p]");
+        assert_eq!(pages[2], "is mine to[linebreak]rule");
+        assert_eq!(
+            def.paginate("El Psy Kongroo", 20, 3),
+            vec!["El Psy Kongroo"]
+        );
+    }
+
+    #[test]
+    fn monospace_width() {
+        let def = get_by_alias("sg0").unwrap();
+        assert_eq!(def.measure_width_monospace("A").unwrap(), 1);
+        assert_eq!(def.measure_width_monospace("Ａ").unwrap(), 2);
+        // The space is encoded as an ideographic space
+        assert_eq!(def.measure_width_monospace("Okabe 岡部").unwrap(), 11);
+        // A single glyph standing for two katakana
+        assert_eq!(def.measure_width_monospace("[キタ]").unwrap(), 2);
+        assert!(def.measure_width_monospace("\u{1F600}").is_err());
+    }
+
+    #[test]
+    fn named_font_widths() {
+        let table = |width| FontWidths::new([('a', width)].iter().cloned().collect());
+        let def = test_def(" ab\u{3000}", "");
+        assert_eq!(def.measure_width("aa", DIALOGUE_FONT).unwrap(), 2);
+
+        let def = def
+            .with_font_widths(DIALOGUE_FONT, table(3))
+            .with_font_widths("menu", table(1));
+        assert_eq!(def.measure_width("aa", DIALOGUE_FONT).unwrap(), 6);
+        assert_eq!(def.measure_width("aa", "menu").unwrap(), 2);
+        // Fonts without a table of their own fall back to the dialogue font
+        assert_eq!(def.measure_width("aab", "title").unwrap(), 7);
+        assert_eq!(
+            def.wrap_to_width("aa aa", 7, DIALOGUE_FONT),
+            vec!["aa", "aa"]
+        );
+        assert_eq!(def.wrap_to_width("aa aa", 7, "menu"), vec!["aa aa"]);
+        assert_eq!(def.paginate("aa aa", 7, 2), vec!["aa[linebreak]aa"]);
+        assert_eq!(def.estimate_line_count("aa aa", 7).unwrap(), 2);
+    }
+
+    #[test]
+    fn estimate_wrapped_line_count() {
+        let def = get_by_alias("sg0").unwrap();
+        let text = "I am the mad scientist Hououin Kyouma and this world is mine to rule";
+        for max_px in &[10, 20, 80] {
+            assert_eq!(
+                def.estimate_line_count(text, *max_px).unwrap(),
+                layout::wrap_to_width(text, *max_px as usize).len()
+            );
+        }
+        assert!(def.estimate_line_count("Tuturu \u{1F600}", 20).is_err());
+    }
+
+    #[test]
+    fn restricted_glyph_set() {
+        let def = get_by_alias("sg0").unwrap();
+        let allowed: BTreeSet<char> = "Tutr!".chars().collect();
+        assert_eq!(
+            def.encode_restricted("Tuturu!", &allowed).unwrap(),
+            def.encode_fixed("Tuturu!", 7, 0).unwrap()
+        );
+        // 'O' is in the charset, but not in the font subset
+        assert_eq!(
+            def.encode_restricted("Okarin", &allowed),
+            Err(text::EncodingError::CharNotAllowed('O'))
+        );
+        // Compound characters are checked by their expansion
+        assert_eq!(
+            def.encode_restricted("T[ü]", &allowed),
+            Err(text::EncodingError::CharNotAllowed('ü'))
+        );
+        let mut allowed = allowed;
+        allowed.insert('ü');
+        assert_eq!(
+            def.encode_restricted("T[ü]", &allowed).unwrap(),
+            def.encode_fixed("T[ü]", 2, 0).unwrap()
+        );
+        // Allowing a character does not make up for the charset lacking it
+        allowed.insert('\u{1F600}');
+        assert!(matches!(
+            def.encode_restricted("Tu\u{1F600}", &allowed),
+            Err(text::EncodingError::CharNotInCharset(_))
+        ));
+    }
+
+    #[test]
+    fn cyrillic_confusables() {
+        let def = get_by_alias("sghd").unwrap();
+        assert!(def.find_confusables("Okabe").is_empty());
+        // A Cyrillic 'а' in place of the Latin one
+        assert_eq!(
+            def.find_confusables("Okаbe \u{1F600}"),
+            vec![ConfusableHit {
+                offset: 2,
+                found: 'а',
+                suggestion: 'a'
+            }]
+        );
+    }
+
+    #[test]
+    fn confusable_overrides() {
+        let def = test_def(" a-ａ", "");
+        assert_eq!(def.suggest_replacement('а'), Some('a'));
+        assert_eq!(def.suggest_replacement('–'), None);
+
+        let overrides = [('а', 'ａ'), ('–', '-'), ('е', 'e')]
+            .iter()
+            .cloned()
+            .collect();
+        let def = def.with_confusable_overrides(overrides);
+        assert_eq!(def.suggest_replacement('а'), Some('ａ'));
+        assert_eq!(def.suggest_replacement('–'), Some('-'));
+        // Overrides the font cannot encode are ignored
+        assert_eq!(def.suggest_replacement('е'), None);
+        assert_eq!(def.find_confusables("а–")[1].suggestion, '-');
+    }
+
+    #[test]
+    fn script_layouts() {
+        use crate::fixtures::load_fixture;
+        use crate::format::StrSeekOrigin;
+
+        let sg0 = get_by_alias("sg0").unwrap().script_layout().unwrap();
+        let rn = get_by_alias("rn").unwrap().script_layout().unwrap();
+        assert_eq!(sg0.offsets_from, StrSeekOrigin::FileStart);
+        assert_eq!((sg0.pointer_width, sg0.entry_width), (4, 4));
+        assert_eq!(rn.offsets_from, StrSeekOrigin::HeapStart);
+        assert_eq!((rn.pointer_width, rn.entry_width), (4, 8));
+
+        let scx = load_fixture("sg0", "sample.scx");
+        let msb = load_fixture("rn", "sample.msb");
+        assert_eq!(sg0.read_string_index(&scx).unwrap().count(), 3);
+        assert!(rn.read_string_index(&msb).unwrap().count() > 0);
+        assert!(sg0.read_string_index(&msb).is_err());
+        assert!(rn.read_string_index(&scx).is_err());
+
+        // Entries are read and written as wide and in the byte order the layout says
+        let entries = [0x00, 0x01, 0x00, 0x10, 0x00, 0x02, 0x00, 0x20];
+        let read = |layout: ScriptLayout| {
+            let (_, entries) = layout.str_index(&entries).unwrap();
+            entries.iter().map(|x| (x.id, x.offset)).collect::<Vec<_>>()
+        };
+        let narrow = ScriptLayout {
+            pointer_width: 2,
+            entry_width: 4,
+            little_endian: false,
+            ..sg0
+        };
+        assert_eq!(read(sg0), vec![(0, 0x1000_0100), (0, 0x2000_0200)]);
+        assert_eq!(read(narrow), vec![(1, 0x10), (2, 0x20)]);
+        let mut buf = std::io::Cursor::new(entries.to_vec());
+        narrow.write_offset(0x30, &mut buf).unwrap();
+        assert_eq!(buf.into_inner()[..4], [0x00, 0x01, 0x00, 0x30]);
+    }
+
+    #[test]
+    fn disk_bytes_roundtrip() {
+        let sg0 = get_by_alias("sg0").unwrap();
+        assert_eq!(sg0.codepoint_to_disk_bytes(0x1E), [0x80, 0x1E]);
+        assert_eq!(sg0.disk_bytes_to_codepoint([0x80, 0x1E]), Some(0x1E));
+
+        let def = test_def(" ab", "").with_script_layout(ScriptLayout {
+            char_code_base: 0x9000,
+            ..Scx::layout()
+        });
+        for cp in [0, 2, 0x0FFF].iter().cloned() {
+            let bytes = def.codepoint_to_disk_bytes(cp);
+            assert_eq!(def.disk_bytes_to_codepoint(bytes), Some(cp));
+        }
+        assert_eq!(def.codepoint_to_disk_bytes(2), [0x90, 0x02]);
+        assert_eq!(def.disk_bytes_to_codepoint([0x80, 0x02]), None);
+
+        let code = text::encode_char(&Char::Regular('b'), &def).unwrap();
+        assert_eq!(code, 0x9002);
+        assert_eq!(text::decode_char(code, &def), Ok(Char::Regular('b')));
+        assert!(text::decode_char(0x8002, &def).is_err());
+    }
+
+    #[test]
+    fn preview_substitutions() {
+        for alias in &["sg0", "sghd"] {
+            let def = get_by_alias(alias).unwrap();
+            let line = "Hou\u{ad}ouin Kyouma [ü]";
+            let preview = def.apply_substitutions(line);
+            assert!(!preview.contains('\u{ad}') && !preview.contains(' '));
+
+            let encode = |s, fullwidth| text::encode_str(&Text(Cow::from(s)), def, fullwidth);
+            assert_eq!(
+                encode(preview.as_str(), false).unwrap(),
+                encode(line, def.prefers_fullwidth()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn bank_count() {
+        let def = get_by_alias("sg0").unwrap();
+        let expected = (def.charset().len() as f64 / DEFAULT_BANK_SIZE as f64).ceil();
+        assert_eq!(def.bank_count(), expected as u16);
+
+        let def = test_def(";bank=128\n ab\ncd", "");
+        assert_eq!(def.bank_count(), 2);
+    }
+
+    #[test]
+    fn missing_for_words() {
+        let def = get_by_alias("sg0").unwrap();
+        assert!(def.missing_for(&["Okabe", "Rintarou"]).is_empty());
+        let missing = def.missing_for(&["Tuturu\u{1F600}", "\u{1F4F1}"]);
+        assert_eq!(
+            missing.into_iter().collect::<Vec<_>>(),
+            vec!['\u{1F4F1}', '\u{1F600}']
+        );
+    }
+
+    #[test]
+    fn compact_charset() {
+        let def = test_def(" a b\ncd", "");
+        assert_eq!(def.charset().len(), 66);
+
+        let (charset, remap) = def.compact_charset();
+        assert_eq!(charset, vec![' ', 'a', 'b', 'c', 'd']);
+        assert!(!charset.contains(&'\0'));
+        assert_eq!(remap.len(), 5);
+        assert_eq!(remap[&0], 0);
+        assert_eq!(remap[&1], 1);
+        assert_eq!(remap[&3], 2);
+        assert_eq!(remap[&64], 3);
+        assert_eq!(remap[&65], 4);
+        assert!(!remap.contains_key(&2));
+    }
+
+    #[test]
+    fn charset_grid_rows() {
+        let def = test_def(" a b\ncd鳳", "");
+        let grid = def.charset_grid();
+        let rows: Vec<_> = grid.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("   0 0000    a · b · ·"));
+        assert_eq!(rows[1], "   1 0040  c d 鳳");
+
+        let def = get_by_alias("sg0").unwrap();
+        let rows = def.charset_grid().lines().count();
+        assert_eq!(
+            rows,
+            (def.charset().len() + DEFAULT_BANK_SIZE - 1) / DEFAULT_BANK_SIZE
+        );
+    }
+
+    #[test]
+    fn ambiguous_expansions() {
+        let def = test_def(
+            " \u{E040}\u{E01C}\u{E041}",
+            "[E01C]=¹⁸\n[E040]=¹⁸\n[E041]=ü",
+        );
+
+        let ambiguous = def.ambiguous_expansions();
+        assert_eq!(ambiguous.len(), 1);
+        assert_eq!(ambiguous["¹⁸"], vec!['\u{E01C}', '\u{E040}']);
+
+        let text = crate::text::Text(Cow::from("[¹⁸]"));
+        let codes = crate::text::encode_str(&text, &def, false).unwrap();
+        assert_eq!(codes, vec![0x8001]);
+    }
+
+    #[test]
+    fn compound_only_chars() {
+        let def = test_def(
+            " ab\u{E01C}\u{E01D}\u{E01E}",
+            "[E01C]=¹⁸\n[E01D]=ab\n[E01E]=a¹",
+        );
+        assert_eq!(
+            def.compound_only_chars().into_iter().collect::<String>(),
+            "¹⁸"
+        );
+    }
+
+    #[test]
+    fn basic_latin_coverage() {
+        let def = test_def(" abｃ", "");
+        assert_eq!(def.charset_coverage_of_block('\0'..='\u{7F}'), (3, 128));
+
+        let sg0 = get_by_alias("sg0").unwrap();
+        let (covered, total) = sg0.charset_coverage_of_block('\0'..='\u{7F}');
+        assert_eq!(total, 128);
+        assert!(covered < total);
+        assert_eq!(sg0.charset_coverage_of_block('A'..='Z'), (26, 26));
+    }
+
+    #[test]
+    fn validate_manifest_issues() {
+        let manifest = GameDefManifest {
+            full_name: "Steins;Gate 0".to_string(),
+            aliases: vec!["sg0".to_string()],
+            reserved_codepoints: None,
+            charset: " ab\u{E01C}".to_string(),
+            compound_chars: "[E01C]=¹⁸\n[E040]=ü\n[E041-E040]=x".to_string(),
+        };
+        assert_eq!(
+            validate_manifest(&manifest),
+            vec![
+                ManifestIssue::CompoundMap(CompoundMapError::Malformed(3)),
+                ManifestIssue::UnresolvedPuaChar('\u{E040}'),
+            ]
+        );
+
+        let manifest = GameDefManifest {
+            reserved_codepoints: Some('\u{E000}'..='\u{E0FF}'),
+            charset: " aba\u{E01C}\u{E01D}".to_string(),
+            compound_chars: "[E01C]=¹⁸".to_string(),
+            ..manifest
+        };
+        // Compound characters belong in the reserved range, ordinary glyphs do not
+        assert_eq!(
+            validate_manifest(&manifest),
+            vec![
+                ManifestIssue::DuplicateGlyph('a'),
+                ManifestIssue::ReservedOverlap('\u{E01D}'),
+            ]
+        );
+
+        let manifest = GameDefManifest {
+            reserved_codepoints: Some('\u{2000}'..='\u{20FF}'),
+            charset: " ab\u{E01C}".to_string(),
+            ..manifest
+        };
+        assert_eq!(
+            validate_manifest(&manifest),
+            vec![ManifestIssue::ReservedOutsidePua('\u{2000}'..='\u{20FF}')]
+        );
+
+        // The range of the Chinese builds
+        let manifest = GameDefManifest {
+            reserved_codepoints: Some('\u{E12F}'..='\u{E2AF}'),
+            ..manifest
+        };
+        assert!(validate_manifest(&manifest).is_empty());
+    }
+
+    #[test]
+    fn charset_pages() {
+        let def = test_def(";bank=16\n;page-shift=8\n ab\n;page\n xy", "");
+        assert_eq!(def.charset().len(), 0x103);
+        assert_eq!(def.charset()[0x100], '\0');
+
+        let text = crate::text::Text(Cow::from("ay"));
+        let codes = crate::text::encode_str(&text, &def, false).unwrap();
+        assert_eq!(codes, vec![0x8001, 0x8102]);
+        let decoded = crate::text::decode_str(&codes, &def, false).unwrap();
+        assert_eq!(decoded, text);
+
+        let (layout, body) = parse_charset_header(";page-shift=14\na\n;page\nb\n;page\nc").unwrap();
+        assert!(matches!(
+            scan_pages(body, &layout, 1),
+            Err(GameDefError::PageOverflow(2))
+        ));
+    }
+
+    #[test]
+    fn charset_source_lines() {
+        let def = test_def(";bank=16\r\n a b\r\n\r\ncd\n;page\nxy", "");
+        assert_eq!(def.source_line_of(0x8000), Some(2));
+        assert_eq!(def.source_line_of(0x8001), Some(2));
+        assert_eq!(def.source_line_of(0x8002), None);
+        assert_eq!(def.source_line_of(0x8003), Some(2));
+        assert_eq!(def.source_line_of(0x8011), Some(4));
+        assert_eq!(def.source_line_of(0x8012), Some(4));
+        assert_eq!(def.source_line_of(0x9000), Some(6));
+        assert_eq!(def.source_line_of(0x9001), Some(6));
+        assert_eq!(def.source_line_of(0xFFFF), None);
+
+        let def = get_by_alias("sg0").unwrap();
+        let code = text::encode_char(&Char::Regular('A'), def).unwrap();
+        let line = def.source_line_of(code).unwrap();
+        let source = ResourceDir::get("sg0/charset.utf8").unwrap();
+        let source = std::str::from_utf8(&source).unwrap();
+        assert!(source.lines().nth(line - 1).unwrap().contains('A'));
+    }
+
+    #[test]
+    fn fullwidth_normalization() {
+        let def = get_by_alias("sg0zhs").unwrap();
+        assert_eq!(
+            def.fullwidth_normalization_diff("ＯｋａｂｅＲｉｎｔａｒｏｕ"),
+            0
+        );
+        // The space and the exclamation mark; the apostrophe is blocklisted
+        assert_eq!(def.fullwidth_normalization_diff("Ｏｋａｂｅ'ｓ ｌａｂ!"), 2);
+        assert_eq!(def.fullwidth_normalization_diff("ab c!"), 5);
+        assert_eq!(def.fullwidth_normalization_diff("[ü]"), 0);
+    }
+
+    #[test]
+    fn width_normalization_is_idempotent() {
+        use text::WidthForm::{Fullwidth, Halfwidth};
+
+        let def = get_by_alias("sg0zhs").unwrap();
+        for line in &["Okabe's lab! [ü]", "Ｏｋａｂｅ＇ｓ　ｌａｂ！", "ab c"] {
+            for target in &[Halfwidth, Fullwidth] {
+                let once = def.normalize_width(line, *target);
+                assert_eq!(def.normalize_width(&once, *target), once);
+            }
+        }
+
+        // Halfwidth text survives a round trip...
+        let line = "Okabe's lab! [ü]";
+        let fullwidth = def.normalize_width(line, Fullwidth);
+        assert_eq!(fullwidth, "Ｏｋａｂｅ'ｓ　ｌａｂ！　[ü]");
+        assert_eq!(def.normalize_width(&fullwidth, Halfwidth), line);
+        // ...but the fullwidth apostrophe is blocklisted
+        assert_eq!(def.normalize_width("＇", Fullwidth), "'");
+    }
+
+    #[test]
+    fn reserved_slots() {
+        let slots = get_by_alias("sg0zhs").unwrap().reserved_slot_status();
+        assert_eq!(slots.len(), 0x181);
+        assert_eq!(slots[0], ('\u{E12F}', SlotStatus::Free));
+        assert_eq!(slots[0x180], ('\u{E2AF}', SlotStatus::Free));
+        assert!(get_by_alias("sg0")
+            .unwrap()
+            .reserved_slot_status()
+            .is_empty());
+
+        let def = test_def_reserved(" \u{E131}", "[E131]=¹⁸", '\u{E130}'..='\u{E132}');
+        assert_eq!(
+            def.reserved_slot_status(),
+            vec![
+                ('\u{E130}', SlotStatus::Free),
+                ('\u{E131}', SlotStatus::Compound("¹⁸".to_string())),
+                ('\u{E132}', SlotStatus::Free),
+            ]
         );
     }
 }
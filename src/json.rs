@@ -0,0 +1,166 @@
+//! The bits of JSON the command line reads and writes: charset descriptions, reports of lines
+//! that could not be encoded and progress records.
+
+use crate::{Issue, Progress};
+
+use std::io::{self, Write};
+use std::path::Path;
+
+impl Issue<'_> {
+    pub(crate) fn to_json(&self) -> String {
+        format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"char\":{},\"codepoint\":{},\"kind\":{}}}",
+            quote(&self.file.to_string_lossy()),
+            self.line,
+            or_null(self.column.map(|c| c.to_string())),
+            or_null(self.ch.map(quote)),
+            or_null(
+                self.ch
+                    .and_then(|ch| ch.chars().next())
+                    .map(|c| (c as u32).to_string())
+            ),
+            quote(self.kind)
+        )
+    }
+}
+
+impl<W: Write> Progress<W> {
+    /// Writes a record of the files processed so far, `path` being the last of them.
+    pub(crate) fn write_json_record(&mut self, path: &Path) -> io::Result<()> {
+        writeln!(
+            self.sink,
+            "{{\"processed\":{},\"total\":{},\"current_file\":{}}}",
+            self.processed,
+            self.total,
+            quote(&path.to_string_lossy())
+        )
+    }
+}
+
+/// Writes `s` as a JSON string literal.
+pub fn quote(s: &str) -> String {
+    let mut buf = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            c if c.is_control() => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
+/// Writes `value`, or `null` if there is none.
+pub fn or_null(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "null".to_string())
+}
+
+/// Reads a charset described as a JSON array of single-glyph strings, in index order,
+/// with `null` or `""` for padding slots.
+pub fn parse_charset(s: &str) -> Result<Vec<char>, (usize, String)> {
+    let line_of = |rest: &str| s[..s.len() - rest.len()].matches('\n').count();
+    let unexpected = |rest: &str| {
+        let found = rest
+            .chars()
+            .next()
+            .map_or("end of input".to_string(), |ch| ch.to_string());
+        (
+            line_of(rest),
+            format!("unexpected '{}' in JSON charset", found),
+        )
+    };
+
+    let mut rest = s
+        .trim_start()
+        .strip_prefix('[')
+        .ok_or_else(|| unexpected(s.trim_start()))?;
+    let mut glyphs = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if glyphs.is_empty() {
+            if let Some(after) = rest.strip_prefix(']') {
+                rest = after;
+                break;
+            }
+        }
+        let (glyph, after) = if let Some(after) = rest.strip_prefix("null") {
+            (String::new(), after)
+        } else {
+            parse_string(rest).ok_or_else(|| unexpected(rest))?
+        };
+        let mut chars = glyph.chars();
+        glyphs.push(match (chars.next(), chars.next()) {
+            (None, _) => '\0',
+            (Some(ch), None) => ch,
+            _ => return Err((line_of(rest), format!("'{}' is not a single glyph", glyph))),
+        });
+        rest = after.trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix(']') {
+            rest = after;
+            break;
+        } else {
+            return Err(unexpected(rest));
+        }
+    }
+    match rest.trim() {
+        "" => Ok(glyphs),
+        rest => Err(unexpected(rest)),
+    }
+}
+
+/// Parses the JSON string literal at the start of `s`, returning it along with the remaining input.
+fn parse_string(s: &str) -> Option<(String, &str)> {
+    let mut buf = String::new();
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    let hex = |chars: &mut std::str::CharIndices| {
+        let digits: String = chars.by_ref().take(4).map(|(_, ch)| ch).collect();
+        u32::from_str_radix(&digits, 16).ok()
+    };
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => return Some((buf, &s[i + 2..])),
+            '\\' => match chars.next()?.1 {
+                'n' => buf.push('\n'),
+                't' => buf.push('\t'),
+                'r' => buf.push('\r'),
+                'b' => buf.push('\u{8}'),
+                'f' => buf.push('\u{c}'),
+                'u' => {
+                    let mut code = hex(&mut chars)?;
+                    if (0xD800..0xDC00).contains(&code) {
+                        // A surrogate pair
+                        if chars.next()?.1 != '\\' || chars.next()?.1 != 'u' {
+                            return None;
+                        }
+                        let low = hex(&mut chars)?;
+                        code = 0x10000 + ((code - 0xD800) << 10) + low.checked_sub(0xDC00)?;
+                    }
+                    buf.push(std::char::from_u32(code)?);
+                }
+                ch => buf.push(ch),
+            },
+            ch => buf.push(ch),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charset_literals() {
+        assert_eq!(
+            parse_charset("[\"a\", \"\\ud83d\\ude00\", null, \"\"]"),
+            Ok(vec!['a', '\u{1F600}', '\0', '\0'])
+        );
+        assert_eq!(parse_charset("[\"a\",\n\"bc\"]").unwrap_err().0, 1);
+        assert_eq!(parse_charset("[]"), Ok(vec![]));
+        assert_eq!(quote("\"a\\b\"\n"), "\"\\\"a\\\\b\\\"\\u000a\"");
+    }
+}
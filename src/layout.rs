@@ -0,0 +1,274 @@
+use crate::text::{self, FULLWIDTH_SPACE, SOFT_HYPHEN};
+
+use std::collections::HashMap;
+
+/// Widths of the glyphs of a font in columns, for fonts whose glyphs are not all one or two
+/// columns wide. Characters without an entry are as wide as `char_width` says.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct FontWidths(HashMap<char, usize>);
+
+impl FontWidths {
+    pub fn new(widths: HashMap<char, usize>) -> Self {
+        Self(widths)
+    }
+
+    /// Returns the width listed for `ch`, if any.
+    pub fn get(&self, ch: char) -> Option<usize> {
+        self.0.get(&ch).cloned()
+    }
+
+    pub fn char_width(&self, ch: char) -> usize {
+        self.get(ch).unwrap_or_else(|| char_width(ch))
+    }
+
+    pub fn measure(&self, s: &str) -> usize {
+        s.chars().map(|ch| self.char_width(ch)).sum()
+    }
+}
+
+/// Returns the display width of a character in columns: two for fullwidth and CJK characters,
+/// one for everything else.
+pub fn char_width(ch: char) -> usize {
+    if is_wide(ch) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Returns the display width of a string in columns.
+pub fn measure(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// Breaks `s` into lines no wider than `width` columns.
+///
+/// Lines are broken at spaces (including the ideographic space), which are dropped at the break,
+/// and after CJK characters. The no-break space is never broken at. Words wider than `width`
+/// are put on a line of their own rather than split.
+///
+/// Soft hyphens mark additional break points. They are invisible, unless a line is broken at one,
+/// in which case it becomes a hyphen at the end of the line.
+pub fn wrap_to_width(s: &str, width: usize) -> Vec<String> {
+    wrap_to_width_with(s, width, None)
+}
+
+/// Like `wrap_to_width`, but when given a thin space glyph (see `GameDef::thin_space`), lines
+/// other than the last one that fall short of `width` by no more than their number of spaces
+/// are padded out with it, one thin space after each of the first spaces.
+pub fn wrap_to_width_with(s: &str, width: usize, thin_space: Option<char>) -> Vec<String> {
+    wrap_to_width_in(s, width, thin_space, &FontWidths::default())
+}
+
+/// Like `wrap_to_width_with`, but measures with the glyph widths of `font`.
+pub fn wrap_to_width_in(
+    s: &str,
+    width: usize,
+    thin_space: Option<char>,
+    font: &FontWidths,
+) -> Vec<String> {
+    let mut lines = wrap(s, width, font);
+    if let Some(thin_space) = thin_space {
+        let last = lines.len() - 1;
+        for line in &mut lines[..last] {
+            justify(line, width, thin_space, font);
+        }
+    }
+
+    lines
+}
+
+/// Counts the lines `wrap_to_width_in` breaks `s` into when measuring with `font`.
+pub fn count_lines(s: &str, width: usize, font: &FontWidths) -> usize {
+    wrap(s, width, font).len()
+}
+
+fn wrap(s: &str, width: usize, font: &FontWidths) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+    let mut pending_space = None;
+    for (word, space) in break_units(s) {
+        let word_width = font.measure(word);
+        let space_width = match pending_space {
+            Some(SOFT_HYPHEN) | None => 0,
+            Some(space) => font.char_width(space),
+        };
+        // A word that may be broken after needs room for the hyphen the break would add
+        let hyphen_width = match space {
+            Some(SOFT_HYPHEN) => font.char_width('-'),
+            _ => 0,
+        };
+        if !line.is_empty() && line_width + space_width + word_width + hyphen_width > width {
+            if pending_space == Some(SOFT_HYPHEN) {
+                line.push('-');
+            }
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        } else if let Some(space) = pending_space.filter(|ch| *ch != SOFT_HYPHEN) {
+            line.push(space);
+            line_width += space_width;
+        }
+        line.push_str(word);
+        line_width += word_width;
+        pending_space = space;
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn justify(line: &mut String, width: usize, thin_space: char, font: &FontWidths) {
+    let slack = width.saturating_sub(font.measure(line));
+    let gaps = line
+        .chars()
+        .filter(|ch| *ch == ' ' || *ch == FULLWIDTH_SPACE)
+        .count();
+    if slack == 0 || slack > gaps {
+        return;
+    }
+
+    let mut justified = String::with_capacity(line.len() + slack * thin_space.len_utf8());
+    let mut remaining = slack;
+    for ch in line.chars() {
+        justified.push(ch);
+        if remaining > 0 && (ch == ' ' || ch == FULLWIDTH_SPACE) {
+            justified.push(thin_space);
+            remaining -= 1;
+        }
+    }
+    *line = justified;
+}
+
+/// Splits `s` at break opportunities, yielding each piece along with the space that ended it.
+fn break_units(s: &str) -> impl Iterator<Item = (&str, Option<char>)> {
+    let mut remaining = s;
+    std::iter::from_fn(move || {
+        if remaining.is_empty() {
+            return None;
+        }
+        for (pos, ch) in remaining.char_indices() {
+            let end = pos + ch.len_utf8();
+            if is_break_point(ch) {
+                let unit = (&remaining[..pos], Some(ch));
+                remaining = &remaining[end..];
+                return Some(unit);
+            } else if is_wide(ch) {
+                let unit = (&remaining[..end], None);
+                remaining = &remaining[end..];
+                return Some(unit);
+            }
+        }
+        let unit = (remaining, None);
+        remaining = "";
+        Some(unit)
+    })
+}
+
+fn is_break_point(ch: char) -> bool {
+    ch == ' ' || ch == FULLWIDTH_SPACE || ch == SOFT_HYPHEN
+}
+
+fn is_wide(ch: char) -> bool {
+    ch == FULLWIDTH_SPACE
+        || text::is_fullwidth_ch(ch)
+        || matches!(ch,
+            '\u{1100}'..='\u{115f}'
+            | '\u{2e80}'..='\u{303e}'
+            | '\u{3041}'..='\u{33ff}'
+            | '\u{3400}'..='\u{4dbf}'
+            | '\u{4e00}'..='\u{9fff}'
+            | '\u{a000}'..='\u{a4cf}'
+            | '\u{ac00}'..='\u{d7a3}'
+            | '\u{f900}'..='\u{faff}'
+            | '\u{fe30}'..='\u{fe4f}'
+            | '\u{ffe0}'..='\u{ffe6}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_at_spaces() {
+        assert_eq!(
+            wrap_to_width("El Psy Kongroo", 8),
+            vec!["El Psy", "Kongroo"]
+        );
+        assert_eq!(wrap_to_width("", 8), vec![""]);
+        assert_eq!(wrap_to_width("Tuturu", 3), vec!["Tuturu"]);
+    }
+
+    #[test]
+    fn count_wrapped_lines() {
+        assert_eq!(count_lines("", 8, &FontWidths::default()), 1);
+        assert_eq!(count_lines("El Psy Kongroo", 8, &FontWidths::default()), 2);
+        assert_eq!(count_lines("鳳凰院凶真", 4, &FontWidths::default()), 3);
+    }
+
+    #[test]
+    fn wrap_soft_hyphens() {
+        assert_eq!(wrap_to_width("Hou\u{ad}ou\u{ad}in", 7), vec!["Hououin"]);
+        assert_eq!(
+            wrap_to_width("Hou\u{ad}ou\u{ad}in Kyouma", 7),
+            vec!["Hououin", "Kyouma"]
+        );
+        assert_eq!(
+            wrap_to_width("Hou\u{ad}ou\u{ad}in", 6),
+            vec!["Houou-", "in"]
+        );
+        assert_eq!(
+            wrap_to_width("Hou\u{ad}ou\u{ad}in", 5),
+            vec!["Hou-", "ouin"]
+        );
+    }
+
+    #[test]
+    fn thin_space_justification() {
+        let s = "El Psy Kongroo Tuturu";
+        assert_eq!(
+            wrap_to_width_with(s, 16, None),
+            vec!["El Psy Kongroo", "Tuturu"]
+        );
+        assert_eq!(
+            wrap_to_width_with(s, 16, Some('\u{E100}')),
+            vec!["El \u{E100}Psy \u{E100}Kongroo", "Tuturu"]
+        );
+        // Too short to be made up for with thin spaces alone
+        assert_eq!(
+            wrap_to_width_with(s, 17, Some('\u{E100}')),
+            vec!["El Psy Kongroo", "Tuturu"]
+        );
+    }
+
+    #[test]
+    fn wrap_cjk_whitespace() {
+        assert_eq!(char_width(FULLWIDTH_SPACE), 2);
+        assert_eq!(char_width('\u{a0}'), 1);
+
+        // Breaks at the ideographic space, which is dropped
+        assert_eq!(
+            wrap_to_width("Okabe\u{3000}Rintarou", 10),
+            vec!["Okabe", "Rintarou"]
+        );
+        assert_eq!(
+            wrap_to_width("Okabe\u{3000}Rintarou", 15),
+            vec!["Okabe\u{3000}Rintarou"]
+        );
+
+        // Never breaks at the no-break space
+        assert_eq!(
+            wrap_to_width("Dr.\u{a0}Nakabachi", 8),
+            vec!["Dr.\u{a0}Nakabachi"]
+        );
+        assert_eq!(
+            wrap_to_width("Hi Dr.\u{a0}Nakabachi", 8),
+            vec!["Hi", "Dr.\u{a0}Nakabachi"]
+        );
+
+        // Breaks between ideographs
+        assert_eq!(wrap_to_width("鳳凰院凶真", 6), vec!["鳳凰院", "凶真"]);
+    }
+}
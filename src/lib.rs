@@ -7,21 +7,38 @@ extern crate nom;
 extern crate rust_embed;
 extern crate termcolor;
 
-mod coz;
-mod format;
-mod gamedef;
-mod sc3;
-mod text;
+#[cfg(feature = "bench")]
+pub mod benching;
+pub mod compose;
+pub mod convert;
+pub mod coz;
+pub mod extract;
+#[cfg(test)]
+mod fixtures;
+pub mod format;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod gamedef;
+pub mod json;
+pub mod layout;
+pub mod po;
+pub mod sc3;
+pub mod source;
+pub mod text;
+pub mod warnings;
+
+pub use coz::CozString;
+pub use gamedef::GameDef;
+pub use sc3::Sc3String;
 
 use clap::{App, AppSettings, Arg, SubCommand};
+use convert::UnmappedPolicy;
 use core::fmt;
-use coz::CozString;
-use gamedef::GameDef;
 use glob::Paths;
 use itertools::{EitherOrBoth, Itertools};
-use sc3::Sc3String;
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
     error,
     fs::{self, OpenOptions},
     io,
@@ -30,6 +47,8 @@ use std::{
 };
 use std::{error::Error, fs::File, path::Path};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use text::{EncodeOptions, Text, TrimPolicy};
+use warnings::Warnings;
 
 #[derive(Debug)]
 enum ProcessingError {
@@ -37,24 +56,125 @@ enum ProcessingError {
     Text(PathBuf, usize, Box<dyn Error>),
     Io(io::Error),
     LineCountMismatch,
+    UnencodableLines(usize),
+    UnknownOffsets(usize),
+    Mismatches(usize),
+    GameNotDetected(PathBuf),
+    TableConflicts(usize),
+    SelfCheckFailed(usize),
 }
 
 impl error::Error for ProcessingError {}
 
+/// How `merge-tables` resolves offsets that the two tables translate differently.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum MergeStrategy {
+    PreferA,
+    PreferB,
+    Error,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum ReportFormat {
+    Human,
+    Json,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum ProgressFormat {
+    Human,
+    Json,
+}
+
+/// Reports the progress of a batch command, one file at a time.
+struct Progress<W: Write> {
+    sink: W,
+    format: ProgressFormat,
+    quiet: bool,
+    processed: usize,
+    total: usize,
+}
+
+/// A machine-readable record of a line that could not be encoded.
+struct Issue<'a> {
+    file: &'a Path,
+    line: usize,
+    column: Option<usize>,
+    ch: Option<&'a str>,
+    kind: &'static str,
+}
+
 lazy_static! {
     static ref SUPPORTED_GAMES: String = {
         let aliases: Vec<_> = gamedef::DEFS.iter().map(|x| x.aliases.join("|")).collect();
         aliases.join(",")
     };
+    static ref SUPPORTED_GAMES_OR_AUTO: String = SUPPORTED_GAMES.clone() + ",auto";
 }
 
-pub fn run() -> Result<(), Box<dyn Error>> {
-    fn validate_game(alias: String) -> Result<(), String> {
-        gamedef::get_by_alias(&alias)
-            .map(|_| ())
-            .ok_or_else(|| format!("try one of the following - {}", *SUPPORTED_GAMES))
+/// Pack name that qualifies the aliases of a definition (`pack:alias`): `builtin` for the
+/// embedded games, and the name of the parent directory for packs loaded with `--pack`.
+fn pack_name(def: &GameDef) -> String {
+    match def.source() {
+        gamedef::GameDefSource::Directory(dir) => dir
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| dir.display().to_string()),
+        _ => "builtin".to_string(),
+    }
+}
+
+/// Looks up a game by alias, trying the packs passed with `--pack` in order before the embedded
+/// definitions. An alias qualified with a pack name (`pack:alias`) is only looked up in that pack.
+fn resolve_game<'a>(packs: &'a [GameDef], arg: &str, alias: &str) -> Result<&'a GameDef, String> {
+    let (pack, alias) = match alias.split_once(':') {
+        Some((pack, alias)) => (Some(pack), alias),
+        None => (None, alias),
+    };
+    let builtin = match pack {
+        Some("builtin") | None => gamedef::get_by_alias(alias),
+        Some(_) => None,
+    };
+    packs
+        .iter()
+        .filter(|def| pack.map_or(true, |pack| pack_name(def) == pack))
+        .find(|def| def.aliases.iter().any(|x| x == alias))
+        .or(builtin)
+        .ok_or_else(|| {
+            let supported = packs
+                .iter()
+                .map(|def| format!("{}:{}", pack_name(def), def.aliases.join("|")))
+                .chain(std::iter::once(SUPPORTED_GAMES.clone()))
+                .join(",");
+            format!(
+                "Invalid value for '<{}>': try one of the following - {}",
+                arg, supported
+            )
+        })
+}
+
+/// Share of recognized glyphs below which `--game auto` refuses to pick a game.
+const AUTO_DETECT_THRESHOLD: f64 = 0.95;
+
+/// Picks the game of a script for `--game auto`. Fails unless the best candidate of
+/// `extract::detect_game` is confident enough and beats the runner-up.
+fn auto_detect_game(script: &Path) -> Result<(&'static GameDef, f64), Box<dyn Error>> {
+    let candidates = extract::detect_game(&fs::read(script)?);
+    match (candidates.first(), candidates.get(1)) {
+        (Some((def, confidence)), runner_up)
+            if *confidence >= AUTO_DETECT_THRESHOLD
+                && runner_up.map_or(true, |(_, next)| next < confidence) =>
+        {
+            Ok((def, *confidence))
+        }
+        _ => Err(Box::new(ProcessingError::GameNotDetected(
+            script.to_owned(),
+        ))),
     }
+}
 
+pub fn run() -> Result<(), Box<dyn Error>> {
     fn parse_glob(arg: &str, pattern: &str) -> Result<Paths, String> {
         glob::glob(pattern).map_err(|err| format!("Invalid value for '<{}>'\n{}", arg, err))
     }
@@ -64,7 +184,6 @@ pub fn run() -> Result<(), Box<dyn Error>> {
             .help(&SUPPORTED_GAMES)
             .index(idx)
             .required(true)
-            .validator(validate_game)
     }
 
     let after_help: String = {
@@ -82,6 +201,30 @@ pub fn run() -> Result<(), Box<dyn Error>> {
         .author("Committee of Zero")
         .version("2.1")
         .after_help(&*after_help)
+        .args(&[
+            Arg::with_name("quiet")
+                .long("quiet")
+                .global(true)
+                .help("Only report errors"),
+            Arg::with_name("progress")
+                .long("progress")
+                .global(true)
+                .takes_value(true)
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .help("Progress output format, json records are written to stderr"),
+            Arg::with_name("pack")
+                .long("pack")
+                .global(true)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("dir")
+                .help(
+                    "Directory of a custom game pack, looked up before the built-in games. \
+                     Can be given multiple times, games are then qualified as <pack>:<alias>",
+                ),
+        ])
         .subcommand(
             SubCommand::with_name("extract-text")
                 .about("Extracts text from one or multiple script files")
@@ -92,7 +235,7 @@ pub fn run() -> Result<(), Box<dyn Error>> {
                         .help("Path to the input file or a glob pattern")
                         .index(1)
                         .required(true),
-                    game_arg(2),
+                    game_arg(2).help(&SUPPORTED_GAMES_OR_AUTO),
                     Arg::with_name("preserve-fullwidth")
                         .long("preserve-fullwidth")
                         .help("Preserve fullwidth characters"),
@@ -112,42 +255,559 @@ pub fn run() -> Result<(), Box<dyn Error>> {
                         .help("Path to the input text file or a glob pattern")
                         .index(2)
                         .required(true),
-                    game_arg(3),
+                    game_arg(3).help(&SUPPORTED_GAMES_OR_AUTO),
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["human", "json"])
+                        .default_value("human")
+                        .help("Output format for lines that could not be encoded"),
+                    Arg::with_name("tab-width")
+                        .long("tab-width")
+                        .takes_value(true)
+                        .min_values(0)
+                        .validator(|width| {
+                            width
+                                .parse::<usize>()
+                                .map(|_| ())
+                                .map_err(|err| err.to_string())
+                        })
+                        .help("Expand tabs to spaces, every <tab-width> columns (4 by default)"),
+                    Arg::with_name("trim")
+                        .long("trim")
+                        .takes_value(true)
+                        .possible_values(&["none", "trailing", "both"])
+                        .default_value("none")
+                        .help("Whitespace to trim from each line of the text files"),
+                    Arg::with_name("recompose")
+                        .long("recompose")
+                        .help("Recompose combining diacritics into precomposed characters"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("subset-list")
+                .about("Lists the character codes used by one or multiple script files")
+                .display_order(3)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("input")
+                        .help("Path to the input file or a glob pattern")
+                        .index(1)
+                        .required(true),
+                    game_arg(2),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("dump-script")
+                .about("Prints an annotated listing of the strings in a script file")
+                .display_order(6)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("input")
+                        .help("Path to the input script file")
+                        .index(1)
+                        .required(true),
+                    game_arg(2),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("reinsert")
+                .about("Replaces the strings of a script file listed in a table of offsets")
+                .display_order(7)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("game")
+                        .long("game")
+                        .takes_value(true)
+                        .required(true)
+                        .help(&SUPPORTED_GAMES),
+                    Arg::with_name("script")
+                        .long("script")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the input script file"),
+                    Arg::with_name("table")
+                        .long("table")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Tab-separated file of hexadecimal string offsets and translations"),
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the output script file"),
+                    Arg::with_name("preserve-trailing-controls")
+                        .long("preserve-trailing-controls")
+                        .help(
+                            "Keep the control codes ending each original string when its \
+                             translation is plain text",
+                        ),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-build")
+                .about("Checks that the strings of a built script match a table of offsets")
+                .display_order(8)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("game")
+                        .long("game")
+                        .takes_value(true)
+                        .required(true)
+                        .help(&SUPPORTED_GAMES),
+                    Arg::with_name("script")
+                        .long("script")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the built script file"),
+                    Arg::with_name("table")
+                        .long("table")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Tab-separated file of hexadecimal string offsets and expected text"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("merge-tables")
+                .about("Merges two translation tables by string offset")
+                .display_order(14)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("a")
+                        .help("Path to the first table")
+                        .index(1)
+                        .required(true),
+                    Arg::with_name("b")
+                        .help("Path to the second table")
+                        .index(2)
+                        .required(true),
+                    Arg::with_name("strategy")
+                        .long("strategy")
+                        .takes_value(true)
+                        .possible_values(&["prefer-a", "prefer-b", "error"])
+                        .default_value("error")
+                        .help("How to resolve offsets that the tables translate differently"),
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the merged table"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("self-check")
+                .about("Checks that the embedded data of every supported game is consistent")
+                .display_order(15)
+                .setting(AppSettings::DisableVersion),
+        )
+        .subcommand(
+            SubCommand::with_name("test-vectors")
+                .about("Prints text along with the character codes it encodes to, for testing other encoders")
+                .display_order(16)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("game")
+                        .long("game")
+                        .takes_value(true)
+                        .required(true)
+                        .help(&SUPPORTED_GAMES),
+                    Arg::with_name("count")
+                        .long("count")
+                        .takes_value(true)
+                        .default_value("100")
+                        .help("Number of vectors to generate"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("progress")
+                .about("Reports how many strings of each script have been translated")
+                .display_order(9)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("game")
+                        .long("game")
+                        .takes_value(true)
+                        .required(true)
+                        .help(&SUPPORTED_GAMES),
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Directory of the source script files"),
+                    Arg::with_name("translated-dir")
+                        .long("translated-dir")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Directory of the translated script files, named like the source"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("build-charset")
+                .about("Builds a charset.utf8 file from a structured charset description")
+                .display_order(12)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["json", "csv"])
+                        .default_value("json")
+                        .help(
+                            "json: an array of glyphs, null or \"\" for padding; \
+                             csv: index,glyph lines",
+                        ),
+                    Arg::with_name("in")
+                        .long("in")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the charset description"),
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the output charset.utf8 file"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("list-games")
+                .about("Lists the supported games, including those of the packs given with --pack")
+                .display_order(11)
+                .setting(AppSettings::DisableVersion),
+        )
+        .subcommand(
+            SubCommand::with_name("controls")
+                .about("Counts the control codes used by the strings of a script")
+                .display_order(10)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("game")
+                        .long("game")
+                        .takes_value(true)
+                        .required(true)
+                        .help(&SUPPORTED_GAMES),
+                    Arg::with_name("script")
+                        .long("script")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the script file"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("show")
+                .about("Shows the character codes a line of text encodes to")
+                .display_order(13)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("game")
+                        .long("game")
+                        .takes_value(true)
+                        .required(true)
+                        .help(&SUPPORTED_GAMES),
+                    Arg::with_name("text")
+                        .long("text")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Text to encode"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("convert")
+                .about("Re-encodes a script file made for one game using the charset of another")
+                .display_order(4)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Game the script was made for"),
+                    Arg::with_name("to")
+                        .long("to")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Game to convert the script for"),
+                    Arg::with_name("in")
+                        .long("in")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the input script file"),
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the output script file"),
+                    Arg::with_name("on-unmapped")
+                        .long("on-unmapped")
+                        .takes_value(true)
+                        .possible_values(&["abort", "substitute"])
+                        .default_value("abort")
+                        .help("What to do with characters the target game cannot represent"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("find-text")
+                .about(
+                    "Lists the lines of one or multiple script files that contain the given text",
+                )
+                .display_order(5)
+                .setting(AppSettings::DisableVersion)
+                .args(&[
+                    Arg::with_name("input")
+                        .help("Path to the input file or a glob pattern")
+                        .index(1)
+                        .required(true),
+                    game_arg(2),
+                    Arg::with_name("text")
+                        .help("Text to search for")
+                        .index(3)
+                        .required(true),
+                    Arg::with_name("across-controls")
+                        .long("across-controls")
+                        .help("Allow matches to span control codes such as line breaks"),
                 ]),
         )
         .get_matches();
 
+    let progress = |matches: &clap::ArgMatches, total| {
+        let format = match matches.value_of("progress") {
+            Some("json") => ProgressFormat::Json,
+            _ => ProgressFormat::Human,
+        };
+        Progress::new(io::stderr(), format, matches.is_present("quiet"), total)
+    };
+
+    let packs = match matches.subcommand() {
+        (_, Some(matches)) => matches
+            .values_of("pack")
+            .into_iter()
+            .flatten()
+            .map(|dir| GameDef::from_dir(Path::new(dir)))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => Vec::new(),
+    };
+    let game = |matches: &clap::ArgMatches, arg: &str| {
+        resolve_game(&packs, arg, matches.value_of(arg).unwrap())
+    };
+    // Like `game`, but `auto` detects the game from the first of `scripts`
+    let game_or_auto =
+        |matches: &clap::ArgMatches, scripts: &[PathBuf]| -> Result<&GameDef, Box<dyn Error>> {
+            match (matches.value_of("game"), scripts.first()) {
+                (Some("auto"), Some(script)) => {
+                    let (def, confidence) = auto_detect_game(script)?;
+                    if !matches.is_present("quiet") {
+                        report_ok(&format!(
+                            "Detected {} ({}) with {:.1}% confidence",
+                            def.full_name,
+                            def.aliases[0],
+                            confidence * 100.0
+                        ));
+                    }
+                    Ok(def)
+                }
+                _ => Ok(game(matches, "game")?),
+            }
+        };
+
     match matches.subcommand() {
         ("extract-text", Some(matches)) => {
             let input = matches.value_of("input").unwrap();
-            let game = matches.value_of("game").unwrap();
-            let gamedef = gamedef::get_by_alias(game).unwrap();
             let keep_fullwidth_chars = matches.is_present("preserve-fullwidth");
-            run_extract_text(parse_glob("input", input)?, gamedef, keep_fullwidth_chars)
+            let paths = parse_glob("input", input)?.collect::<Result<Vec<_>, _>>()?;
+            let gamedef = game_or_auto(matches, &paths)?;
+            let progress = progress(matches, paths.len());
+            run_extract_text(paths, gamedef, keep_fullwidth_chars, progress)
         }
         ("replace-text", Some(matches)) => {
             let scripts = matches.value_of("scripts").unwrap();
             let txts = matches.value_of("text-files").unwrap();
-            let game = matches.value_of("game").unwrap();
-            let gamedef = gamedef::get_by_alias(game).unwrap();
+            let format = match matches.value_of("format") {
+                Some("json") => ReportFormat::Json,
+                _ => ReportFormat::Human,
+            };
+            let options = EncodeOptions {
+                tab_width: match matches.value_of("tab-width") {
+                    Some(width) => Some(width.parse()?),
+                    None if matches.is_present("tab-width") => Some(text::DEFAULT_TAB_WIDTH),
+                    None => None,
+                },
+                trim: match matches.value_of("trim") {
+                    Some("trailing") => TrimPolicy::Trailing,
+                    Some("both") => TrimPolicy::Both,
+                    _ => TrimPolicy::None,
+                },
+                recompose: matches.is_present("recompose"),
+            };
 
+            let scripts = parse_glob("scripts", scripts)?.collect::<Result<Vec<_>, _>>()?;
+            let gamedef = game_or_auto(matches, &scripts)?;
+            let progress = progress(matches, scripts.len());
             run_replace_text(
-                parse_glob("scripts", scripts)?,
+                scripts,
                 parse_glob("text-files", txts)?,
                 &gamedef,
+                format,
+                &options,
+                progress,
+            )
+        }
+        ("subset-list", Some(matches)) => {
+            let input = matches.value_of("input").unwrap();
+            let gamedef = game(matches, "game")?;
+            run_subset_list(parse_glob("input", input)?, gamedef)
+        }
+        ("find-text", Some(matches)) => {
+            let input = matches.value_of("input").unwrap();
+            let gamedef = game(matches, "game")?;
+            let needle = matches.value_of("text").unwrap();
+            let across_controls = matches.is_present("across-controls");
+            run_find_text(
+                parse_glob("input", input)?,
+                gamedef,
+                needle,
+                across_controls,
             )
         }
+        ("dump-script", Some(matches)) => {
+            let input = matches.value_of("input").unwrap();
+            let gamedef = game(matches, "game")?;
+            print!("{}", extract::dump_script(&fs::read(input)?, gamedef)?);
+            Ok(())
+        }
+        ("convert", Some(matches)) => {
+            let from = game(matches, "from")?;
+            let to = game(matches, "to")?;
+            let input = Path::new(matches.value_of("in").unwrap());
+            let output = Path::new(matches.value_of("out").unwrap());
+            let policy = match matches.value_of("on-unmapped") {
+                Some("substitute") => UnmappedPolicy::Substitute,
+                _ => UnmappedPolicy::Abort,
+            };
+
+            let mut progress = progress(matches, 1);
+            progress.start(input);
+            let res = convert_script(input, output, from, to, policy);
+            Ok(progress.finish(input, res)?)
+        }
+        ("reinsert", Some(matches)) => {
+            let gamedef = game(matches, "game")?;
+            let script = Path::new(matches.value_of("script").unwrap());
+            let table = Path::new(matches.value_of("table").unwrap());
+            let output = Path::new(matches.value_of("out").unwrap());
+
+            let mut progress = progress(matches, 1);
+            progress.start(script);
+            let preserve_trailing_controls = matches.is_present("preserve-trailing-controls");
+            let res = reinsert(script, table, output, gamedef, preserve_trailing_controls);
+            Ok(progress.finish(script, res)?)
+        }
+        ("merge-tables", Some(matches)) => {
+            let a = Path::new(matches.value_of("a").unwrap());
+            let b = Path::new(matches.value_of("b").unwrap());
+            let output = Path::new(matches.value_of("out").unwrap());
+            let strategy = match matches.value_of("strategy") {
+                Some("prefer-a") => MergeStrategy::PreferA,
+                Some("prefer-b") => MergeStrategy::PreferB,
+                _ => MergeStrategy::Error,
+            };
+            let message = run_merge_tables(a, b, output, strategy)?;
+            if !matches.is_present("quiet") {
+                report_ok(&message);
+            }
+            Ok(())
+        }
+        ("self-check", Some(_)) => {
+            let issues = gamedef::self_check();
+            for (alias, issue) in &issues {
+                report(&format!("Error: {}: {}.", alias, issue));
+            }
+            if !issues.is_empty() {
+                return Err(Box::new(ProcessingError::SelfCheckFailed(issues.len())));
+            }
+            if !matches.is_present("quiet") {
+                report_ok("The data of all supported games is consistent");
+            }
+            Ok(())
+        }
+        ("test-vectors", Some(matches)) => {
+            let gamedef = game(matches, "game")?;
+            let count = matches.value_of("count").unwrap().parse()?;
+            for (input, codes) in gamedef::generate_test_vectors(gamedef, count) {
+                let codes: Vec<_> = codes.iter().map(|code| format!("{:04X}", code)).collect();
+                println!("{}\t{}", input, codes.join(" "));
+            }
+            Ok(())
+        }
+        ("progress", Some(matches)) => {
+            let gamedef = game(matches, "game")?;
+            let dir = Path::new(matches.value_of("dir").unwrap());
+            let translated_dir = Path::new(matches.value_of("translated-dir").unwrap());
+            run_translation_progress(dir, translated_dir, gamedef)
+        }
+        ("build-charset", Some(matches)) => {
+            let input = Path::new(matches.value_of("in").unwrap());
+            let output = Path::new(matches.value_of("out").unwrap());
+            let description = fs::read_to_string(input)?;
+            let glyphs = match matches.value_of("format") {
+                Some("csv") => parse_charset_csv(&description),
+                _ => json::parse_charset(&description),
+            }
+            .map_err(|(line, err)| ProcessingError::Text(input.to_owned(), line, err.into()))?;
+            fs::write(output, gamedef::build_charset(&glyphs))?;
+            if !matches.is_present("quiet") {
+                report_ok(&format!("Wrote {} slots to {:?}", glyphs.len(), output));
+            }
+            Ok(())
+        }
+        ("list-games", Some(_)) => {
+            let defs = packs.iter().chain(gamedef::DEFS.iter());
+            for def in defs {
+                println!(
+                    "{}:{}\t{}",
+                    pack_name(def),
+                    def.aliases.join("|"),
+                    def.full_name
+                );
+            }
+            Ok(())
+        }
+        ("controls", Some(matches)) => {
+            let gamedef = game(matches, "game")?;
+            let script = fs::read(matches.value_of("script").unwrap())?;
+            for (code, count) in extract::control_code_census(&script, gamedef)? {
+                println!("{}\t{}", code, count);
+            }
+            Ok(())
+        }
+        ("show", Some(matches)) => {
+            let gamedef = game(matches, "game")?;
+            let text = Text(Cow::from(matches.value_of("text").unwrap()));
+            print!(
+                "{}",
+                text::show_encoding(&text, gamedef, gamedef.prefers_fullwidth())?
+            );
+            Ok(())
+        }
+        ("verify-build", Some(matches)) => {
+            let gamedef = game(matches, "game")?;
+            let script = Path::new(matches.value_of("script").unwrap());
+            let table = Path::new(matches.value_of("table").unwrap());
+            let message = verify_build(script, table, gamedef)?;
+            if !matches.is_present("quiet") {
+                report_ok(&message);
+            }
+            Ok(())
+        }
         _ => Ok(()),
     }
 }
 
 fn run_extract_text(
-    paths: Paths,
+    paths: Vec<PathBuf>,
     gamedef: &GameDef,
     keep_fullwidth_chars: bool,
+    mut progress: Progress<impl Write>,
 ) -> Result<(), Box<dyn Error>> {
-    Ok(for entry in paths {
-        let path = entry?;
+    for path in paths {
         let out_dir = if let Some(script_dir) = path.parent() {
             let out_dir = script_dir.join("txt");
             fs::create_dir_all(&out_dir)?;
@@ -162,35 +822,145 @@ fn run_extract_text(
             continue;
         };
 
-        println!("Processing {:?}...", path);
+        progress.start(&path);
         let ext = ".".to_owned() + &path.extension().unwrap_or_default().to_str().unwrap() + ".txt";
         let output = out_dir.join(stem + &ext);
-        if let Err(err) = extract_text(&path, &output, gamedef, keep_fullwidth_chars) {
-            report_err(err)
-        }
-    })
+        let res = extract_text(&path, &output, gamedef, keep_fullwidth_chars);
+        progress.finish(&path, res)?;
+    }
+    Ok(())
 }
 
 fn run_replace_text(
-    scripts: Paths,
+    scripts: Vec<PathBuf>,
     text_files: Paths,
     game: &GameDef,
+    format: ReportFormat,
+    options: &EncodeOptions,
+    mut progress: Progress<impl Write>,
 ) -> Result<(), Box<dyn Error>> {
     let text_files: Vec<_> = text_files.map(|x| x.unwrap()).collect();
-    Ok(for res in scripts {
-        let script_path = res?;
-        println!("Processing {:?}", script_path);
+    for script_path in scripts {
+        progress.start(&script_path);
         let script_fname = script_path.file_name();
         let script_stem = script_path.file_stem();
         let txt_path = text_files.iter().find(|p| {
             let stem = p.file_stem();
             stem == script_stem || stem == script_fname
         });
-        if let Some(txt_path) = txt_path {
-            if let Err(err) = replace_text(script_path, txt_path, &game) {
-                report_err(err)
-            }
+        let res = if let Some(txt_path) = txt_path {
+            replace_text(&script_path, txt_path, game, format, options)
+        } else {
+            Ok("No matching text file found.".to_string())
+        };
+        progress.finish(&script_path, res)?;
+    }
+    Ok(())
+}
+
+fn run_subset_list(paths: Paths, gamedef: &GameDef) -> Result<(), Box<dyn Error>> {
+    let scripts = paths
+        .map(|entry| Ok(fs::read(entry?)?))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    let scripts: Vec<_> = scripts.iter().map(Vec::as_slice).collect();
+    for code in extract::used_codepoints(&scripts, gamedef)? {
+        println!("{:04X}", code);
+    }
+    Ok(())
+}
+
+fn run_find_text(
+    paths: Paths,
+    gamedef: &GameDef,
+    needle: &str,
+    across_controls: bool,
+) -> Result<(), Box<dyn Error>> {
+    for path in paths {
+        let path = path?;
+        let script = fs::read(&path)?;
+        for i in extract::find_text(&script, gamedef, needle, across_controls)? {
+            println!("{}:{}", path.display(), i + 1);
+        }
+    }
+    Ok(())
+}
+
+fn run_translation_progress(
+    dir: &Path,
+    translated_dir: &Path,
+    gamedef: &GameDef,
+) -> Result<(), Box<dyn Error>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .filter(|path| !matches!(path, Ok(path) if path.is_dir()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    let (mut total, mut changed) = (0, 0);
+    for path in paths {
+        let name = path.file_name().unwrap();
+        let translated = translated_dir.join(name);
+        if !translated.exists() {
+            println!("{}: not translated", name.to_string_lossy());
+            continue;
         }
+
+        let progress =
+            extract::translation_progress(&fs::read(&path)?, &fs::read(&translated)?, gamedef)?;
+        println!(
+            "{}: {}/{} ({:.1}%)",
+            name.to_string_lossy(),
+            progress.changed,
+            progress.total,
+            progress.percentage()
+        );
+        total += progress.total;
+        changed += progress.changed;
+    }
+
+    if total > 0 {
+        println!(
+            "Total: {}/{} ({:.1}%)",
+            changed,
+            total,
+            changed as f64 * 100.0 / total as f64
+        );
+    }
+    Ok(())
+}
+
+fn convert_script(
+    input: &Path,
+    output: &Path,
+    from: &GameDef,
+    to: &GameDef,
+    policy: UnmappedPolicy,
+) -> Result<String, Box<dyn Error>> {
+    let script = format::open(File::open(input)?)?;
+    let mut changes = HashMap::new();
+    let mut warnings = Warnings::new();
+    for (i, handle) in script.string_index().iter().enumerate() {
+        let s = script.read_string(handle)?;
+        let converted = convert::convert_string(&s, from, to, policy, &mut warnings)
+            .map_err(|err| ProcessingError::Script(input.to_owned(), i, Box::new(err)))?;
+        changes.insert(i, converted);
+    }
+    drop(script);
+
+    if input != output {
+        fs::copy(input, output)?;
+    }
+    let file = OpenOptions::new().read(true).write(true).open(output)?;
+    format::open(file)?.replace_strings(&changes)?;
+
+    Ok(if warnings.is_empty() {
+        format!("Successfully converted {} lines.", changes.len())
+    } else {
+        format!(
+            "Converted {} lines with warnings: {}",
+            changes.len(),
+            warnings.iter().unique().join(", ")
+        )
     })
 }
 
@@ -199,7 +969,7 @@ fn extract_text(
     out: &impl AsRef<Path>,
     gamedef: &GameDef,
     keep_fullwidth_chars: bool,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<String, Box<dyn Error>> {
     let script = format::open(File::open(script_path)?)?;
     let txt = File::create(out)?;
     let mut writer = BufWriter::new(txt);
@@ -215,19 +985,296 @@ fn extract_text(
         writeln!(writer, "{}", serialized)?;
     }
 
-    if table.count() > 0 {
-        report_ok(&format!("Sucessfully extracted {} lines.", table.count()));
+    Ok(if table.count() > 0 {
+        format!("Sucessfully extracted {} lines.", table.count())
     } else {
-        report_ok("No text data to be extracted.");
+        "No text data to be extracted.".to_string()
+    })
+}
+
+/// A row of a translation table.
+struct TableRow {
+    line: usize,
+    offset: u32,
+    text: String,
+}
+
+/// Reads a table of `offset\ttext` rows, with string offsets in hexadecimal.
+/// Empty lines are skipped.
+fn read_table(path: &Path) -> Result<Vec<TableRow>, Box<dyn Error>> {
+    let mut rows = Vec::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let row = line.split_once('\t').and_then(|(offset, text)| {
+            let offset = offset.trim_start_matches("0x");
+            let offset = u32::from_str_radix(offset, 16).ok()?;
+            Some(TableRow {
+                line: i,
+                offset,
+                text: text.to_string(),
+            })
+        });
+        match row {
+            Some(row) => rows.push(row),
+            None => {
+                let err = format!("expected a hexadecimal offset and a tab, found '{}'", line);
+                return Err(Box::new(ProcessingError::Text(
+                    path.to_owned(),
+                    i,
+                    err.into(),
+                )));
+            }
+        }
     }
-    Ok(())
+    Ok(rows)
+}
+
+/// An offset that two tables translate differently, with the line of each row.
+#[derive(Debug, Eq, PartialEq)]
+struct TableConflict {
+    offset: u32,
+    line_a: usize,
+    line_b: usize,
+}
+
+/// Merges two tables by offset into rows sorted by offset, resolving conflicting rows by
+/// `strategy`. Under `MergeStrategy::Error`, the rows of `a` are kept. The conflicts are
+/// returned either way.
+fn merge_tables(
+    a: Vec<TableRow>,
+    b: Vec<TableRow>,
+    strategy: MergeStrategy,
+) -> (Vec<TableRow>, Vec<TableConflict>) {
+    let mut merged: BTreeMap<u32, TableRow> = a.into_iter().map(|row| (row.offset, row)).collect();
+    let mut conflicts = Vec::new();
+    for row in b {
+        match merged.get(&row.offset) {
+            Some(existing) if existing.text != row.text => {
+                conflicts.push(TableConflict {
+                    offset: row.offset,
+                    line_a: existing.line,
+                    line_b: row.line,
+                });
+                if strategy == MergeStrategy::PreferB {
+                    merged.insert(row.offset, row);
+                }
+            }
+            Some(_) => {}
+            None => {
+                merged.insert(row.offset, row);
+            }
+        }
+    }
+    (merged.into_iter().map(|(_, row)| row).collect(), conflicts)
+}
+
+fn run_merge_tables(
+    a: &Path,
+    b: &Path,
+    output: &Path,
+    strategy: MergeStrategy,
+) -> Result<String, Box<dyn Error>> {
+    let (rows, conflicts) = merge_tables(read_table(a)?, read_table(b)?, strategy);
+    if strategy == MergeStrategy::Error && !conflicts.is_empty() {
+        for conflict in &conflicts {
+            let err = format!(
+                "offset {:#X} is translated differently at line {} of {}",
+                conflict.offset,
+                conflict.line_a + 1,
+                a.display()
+            );
+            report_err(Box::new(ProcessingError::Text(
+                b.to_owned(),
+                conflict.line_b,
+                err.into(),
+            )));
+        }
+        return Err(Box::new(ProcessingError::TableConflicts(conflicts.len())));
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    for row in &rows {
+        writeln!(writer, "{:#X}\t{}", row.offset, row.text)?;
+    }
+    writer.flush()?;
+
+    Ok(format!(
+        "Merged {} rows, resolving {} conflict(s).",
+        rows.len(),
+        conflicts.len()
+    ))
+}
+
+/// Reads a charset described as `index,glyph` lines, a header line being allowed.
+/// Slots that are not listed are padding. Errors carry the line they occurred on.
+fn parse_charset_csv(s: &str) -> Result<Vec<char>, (usize, String)> {
+    let mut glyphs = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+        if line.is_empty() || (i == 0 && line.starts_with("index,")) {
+            continue;
+        }
+        let entry = line.split_once(',').and_then(|(index, glyph)| {
+            let index: usize = index.trim().parse().ok()?;
+            let mut chars = glyph.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => Some((index, ch)),
+                _ => None,
+            }
+        });
+        match entry {
+            Some((index, ch)) => {
+                if glyphs.len() <= index {
+                    glyphs.resize(index + 1, '\0');
+                }
+                glyphs[index] = ch;
+            }
+            None => {
+                return Err((
+                    i,
+                    format!("expected an index and a glyph, found '{}'", line),
+                ))
+            }
+        }
+    }
+    Ok(glyphs)
+}
+
+fn reinsert(
+    script_file: &Path,
+    table_file: &Path,
+    output: &Path,
+    gamedef: &GameDef,
+    preserve_trailing_controls: bool,
+) -> Result<String, Box<dyn Error>> {
+    let rows = read_table(table_file)?;
+    let script = fs::read(script_file)?;
+    let index = format::read_string_index(&script)?;
+    let offsets: HashMap<_, _> = index
+        .iter()
+        .enumerate()
+        .map(|(i, handle)| (handle.0.start, i))
+        .collect();
+
+    let mut replacements = Vec::new();
+    let mut unknown = 0;
+    for row in rows {
+        match offsets.get(&row.offset) {
+            Some(i) => replacements.push((*i, row)),
+            None => {
+                unknown += 1;
+                let err = format!("no string starts at offset {:#X}", row.offset);
+                report_err(Box::new(ProcessingError::Text(
+                    table_file.to_owned(),
+                    row.line,
+                    err.into(),
+                )));
+            }
+        }
+    }
+
+    let lines: Vec<_> = replacements
+        .iter()
+        .map(|(_, row)| (row.line, row.text.clone()))
+        .collect();
+    let failures = coz::validate_replacements(&lines, gamedef);
+    let unencodable = failures.len();
+    for (line, err) in failures {
+        let err = ProcessingError::Text(table_file.to_owned(), line, Box::new(err));
+        report_err(Box::new(err));
+    }
+    if unknown > 0 {
+        return Err(Box::new(ProcessingError::UnknownOffsets(unknown)));
+    }
+    if unencodable > 0 {
+        return Err(Box::new(ProcessingError::UnencodableLines(unencodable)));
+    }
+
+    let fullwidth = gamedef.prefers_fullwidth();
+    let markup: Vec<_> = replacements
+        .iter()
+        .map(|(_, row)| CozString(row.text.as_str().into()))
+        .collect();
+    let changes = replacements
+        .iter()
+        .zip(&markup)
+        .map(|((i, row), s)| {
+            let to_err =
+                |err: Box<dyn Error>| ProcessingError::Text(table_file.to_owned(), row.line, err);
+            let s =
+                Sc3String::deserialize(s, gamedef, fullwidth).map_err(|err| to_err(err.into()))?;
+            if !preserve_trailing_controls {
+                return Ok((*i, s));
+            }
+            let handle = index.get(*i).unwrap();
+            let data = handle.slice(&script).map_err(|err| to_err(err.into()))?;
+            let original = Sc3String(data.into());
+            let s = sc3::inherit_trailing_controls(&s, &original, gamedef.string_framing())
+                .map_err(|err| to_err(err.into()))?;
+            Ok((*i, s))
+        })
+        .collect::<Result<HashMap<_, _>, ProcessingError>>()?;
+
+    if script_file != output {
+        fs::copy(script_file, output)?;
+    }
+    let file = OpenOptions::new().read(true).write(true).open(output)?;
+    format::open(file)?.replace_strings(&changes)?;
+
+    Ok(format!(
+        "Successfully replaced {} out of {} lines.",
+        changes.len(),
+        index.count()
+    ))
+}
+
+/// Compares the strings of a built script with the expected text, reporting each mismatch.
+/// The offsets in the table refer to the built script.
+fn verify_build(
+    script_file: &Path,
+    table_file: &Path,
+    gamedef: &GameDef,
+) -> Result<String, Box<dyn Error>> {
+    let rows = read_table(table_file)?;
+    let script = fs::read(script_file)?;
+    let strings: HashMap<_, _> = extract::extract_strings(&script, gamedef)?
+        .into_iter()
+        .map(|s| (s.offset, s.to_markup()))
+        .collect();
+
+    let mut mismatches = 0;
+    for row in &rows {
+        // Lines may have been encoded with or without fullwidth conversion
+        let text = Text(row.text.as_str().into());
+        let expected = text::to_halfwidth(&text, &gamedef.encoding_maps);
+        let err = match strings.get(&row.offset) {
+            Some(actual) if actual == expected.as_str() => continue,
+            Some(actual) => format!("string at offset {:#X} reads '{}'", row.offset, actual),
+            None => format!("no string starts at offset {:#X}", row.offset),
+        };
+        mismatches += 1;
+        report_err(Box::new(ProcessingError::Text(
+            table_file.to_owned(),
+            row.line,
+            err.into(),
+        )));
+    }
+
+    if mismatches > 0 {
+        return Err(Box::new(ProcessingError::Mismatches(mismatches)));
+    }
+    Ok(format!("All {} lines match.", rows.len()))
 }
 
 fn replace_text(
     script_file: impl AsRef<Path>,
     text_file: impl AsRef<Path>,
     gamedef: &GameDef,
-) -> Result<(), Box<dyn Error>> {
+    format: ReportFormat,
+    options: &EncodeOptions,
+) -> Result<String, Box<dyn Error>> {
     let file = OpenOptions::new()
         .read(true)
         .write(true)
@@ -239,7 +1286,10 @@ fn replace_text(
         .string_index()
         .iter()
         .map(|x| script.read_string(x))
-        .zip_longest(txt.lines().map(|res| res.map(|s| CozString(s.into()))));
+        .zip_longest(
+            txt.lines()
+                .map(|res| res.map(|s| CozString(options.normalize(&s).into_owned().into()))),
+        );
 
     let mut changes = Vec::new();
 
@@ -282,6 +1332,25 @@ fn replace_text(
         }
     }
 
+    let replacements: Vec<_> = changes.iter().map(|(i, s)| (*i, s.0.to_string())).collect();
+    let failures = coz::validate_replacements(&replacements, gamedef);
+    if !failures.is_empty() {
+        let count = failures.len();
+        for (i, err) in failures {
+            match format {
+                ReportFormat::Human => report_err(Box::new(txt_err(Box::new(err), i))),
+                ReportFormat::Json => {
+                    let line = &replacements.iter().find(|(j, _)| *j == i).unwrap().1;
+                    println!(
+                        "{}",
+                        Issue::new(text_file.as_ref(), i, line, &err).to_json()
+                    );
+                }
+            }
+        }
+        return Err(Box::new(ProcessingError::UnencodableLines(count)));
+    }
+
     let process_change = |i, s| {
         let index = &script.string_index();
         let orig = script.read_string(index.get(i).unwrap())?;
@@ -318,16 +1387,15 @@ fn replace_text(
 
     script.replace_strings(&changes)?;
 
-    if !changes.is_empty() {
-        report_ok(&format!(
+    Ok(if !changes.is_empty() {
+        format!(
             "Successfully replaced {} out of {} lines.",
             changes.len(),
             script.string_index().count()
-        ));
+        )
     } else {
-        report_ok("No changes found.");
-    }
-    Ok(())
+        "No changes found.".to_string()
+    })
 }
 
 fn equivalent(
@@ -352,6 +1420,73 @@ fn equivalent(
     )
 }
 
+impl<'a> Issue<'a> {
+    fn new(file: &'a Path, line: usize, text: &str, err: &'a coz::Error) -> Self {
+        let ch = match err {
+            coz::Error::TextEncoding(text::EncodingError::CharNotInCharset(ch)) => {
+                Some(ch.as_str())
+            }
+            _ => None,
+        };
+        let kind = match err {
+            coz::Error::Parsing(coz::ParseError::MissingAttribute(_)) => "missing-attribute",
+            coz::Error::Parsing(coz::ParseError::UnexpectedAttribute(_)) => "unexpected-attribute",
+            coz::Error::Parsing(coz::ParseError::IllegalAttributeValue(_)) => {
+                "illegal-attribute-value"
+            }
+            coz::Error::TextEncoding(text::EncodingError::CharNotInCharset(_)) => {
+                "char-not-in-charset"
+            }
+            coz::Error::TextEncoding(_) => "illegal-char-code",
+            coz::Error::Serialization(_) => "serialization",
+            coz::Error::RecordTooLong(..) => "record-too-long",
+        };
+        let column = ch
+            .and_then(|ch| text.find(ch))
+            .map(|pos| text[..pos].chars().count() + 1);
+
+        Issue {
+            file,
+            line: line + 1,
+            column,
+            ch,
+            kind,
+        }
+    }
+}
+
+impl<W: Write> Progress<W> {
+    fn new(sink: W, format: ProgressFormat, quiet: bool, total: usize) -> Self {
+        Self {
+            sink,
+            format,
+            quiet,
+            processed: 0,
+            total,
+        }
+    }
+
+    fn start(&self, path: &Path) {
+        if self.format == ProgressFormat::Human && !self.quiet {
+            println!("Processing {:?}...", path);
+        }
+    }
+
+    fn finish(&mut self, path: &Path, res: Result<String, Box<dyn Error>>) -> io::Result<()> {
+        self.processed += 1;
+        match res {
+            Ok(message) if !self.quiet => report_ok(&message),
+            Ok(_) => {}
+            Err(err) => report_err(err),
+        }
+
+        if self.format == ProgressFormat::Json {
+            self.write_json_record(path)?;
+        }
+        Ok(())
+    }
+}
+
 fn report(message: &str) {
     let mut stderr = StandardStream::stderr(ColorChoice::Always);
     stderr
@@ -397,6 +1532,32 @@ impl fmt::Display for ProcessingError {
                 f,
                 "The number of lines in the text file has to match that of the script file"
             ),
+            ProcessingError::UnencodableLines(count) => write!(
+                f,
+                "{} line(s) could not be encoded, the script file was left untouched",
+                count
+            ),
+            ProcessingError::UnknownOffsets(count) => write!(
+                f,
+                "{} offset(s) do not match any string, the script file was left untouched",
+                count
+            ),
+            ProcessingError::Mismatches(count) => {
+                write!(f, "{} line(s) do not match the expected text", count)
+            }
+            ProcessingError::TableConflicts(count) => write!(
+                f,
+                "{} offset(s) are translated differently, no table was written",
+                count
+            ),
+            ProcessingError::SelfCheckFailed(count) => {
+                write!(f, "{} problem(s) found in the embedded game data", count)
+            }
+            ProcessingError::GameNotDetected(path) => write!(
+                f,
+                "Could not tell which game {} is for, please specify it with --game",
+                path.display()
+            ),
         }
     }
 }
@@ -406,3 +1567,320 @@ impl From<io::Error> for ProcessingError {
         ProcessingError::Io(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_script_file() {
+        let (from, to) = (
+            gamedef::get_by_alias("sghd").unwrap(),
+            gamedef::get_by_alias("sg0").unwrap(),
+        );
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sghd/sample.scx");
+        let output = std::env::temp_dir().join("sc3tools_convert_script_file.scx");
+        let message = convert_script(&input, &output, from, to, UnmappedPolicy::Abort).unwrap();
+        assert_eq!(message, "Successfully converted 3 lines.");
+
+        let converted = extract::extract_strings(&fs::read(&output).unwrap(), to).unwrap();
+        let original = extract::extract_strings(&fs::read(&input).unwrap(), from).unwrap();
+        assert_eq!(converted, original);
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn reinsert_from_table() {
+        let gamedef = gamedef::get_by_alias("sg0").unwrap();
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sg0/sample.scx");
+        let table = std::env::temp_dir().join("sc3tools_reinsert_from_table.tsv");
+        let output = std::env::temp_dir().join("sc3tools_reinsert_from_table.scx");
+
+        fs::write(
+            &table,
+            "0000001C\tTuturu\u{266A} Mayushii\n\n0x55\tOkarin?\n",
+        )
+        .unwrap();
+        let message = reinsert(&input, &table, &output, gamedef, false).unwrap();
+        assert_eq!(message, "Successfully replaced 2 out of 3 lines.");
+        let strings = extract::extract_strings(&fs::read(&output).unwrap(), gamedef).unwrap();
+        let texts: Vec<_> = strings.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts[0], "Tuturu\u{266A} Mayushii");
+        assert_eq!(texts[2], "Okarin?");
+        fs::remove_file(&output).unwrap();
+
+        fs::write(&table, "1C\tTuturu\u{1F600}\n3C\tKyouma\n").unwrap();
+        assert!(reinsert(&input, &table, &output, gamedef, false).is_err());
+        assert!(!output.exists());
+        fs::remove_file(&table).unwrap();
+    }
+
+    #[test]
+    fn verify_corrupted_build() {
+        let gamedef = gamedef::get_by_alias("sg0").unwrap();
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sg0/sample.scx");
+        let table = std::env::temp_dir().join("sc3tools_verify_corrupted_build.tsv");
+        let script = std::env::temp_dir().join("sc3tools_verify_corrupted_build.scx");
+        fs::write(
+            &table,
+            "1C\tTuturu\u{266A} Okarin\u{3002}\n\
+             3B\t[ruby-base]鳳凰院凶真[ruby-text-start]Kyouma[ruby-text-end]\n\
+             55\t[name]Mayuri[line]Okarin!\n",
+        )
+        .unwrap();
+        let message = verify_build(&input, &table, gamedef).unwrap();
+        assert_eq!(message, "All 3 lines match.");
+        // Whether a line was converted to fullwidth does not matter
+        fs::write(&table, "1C\tＴｕｔｕｒｕ\u{266A}\u{3000}Okarin\u{3002}\n").unwrap();
+        assert!(verify_build(&input, &table, gamedef).is_ok());
+        fs::write(
+            &table,
+            "1C\tTuturu\u{266A} Okarin\u{3002}\n\
+             3B\t[ruby-base]鳳凰院凶真[ruby-text-start]Kyouma[ruby-text-end]\n\
+             55\t[name]Mayuri[line]Okarin!\n",
+        )
+        .unwrap();
+
+        // Swap the first two characters of the last line
+        let mut data = fs::read(&input).unwrap();
+        data.swap(0x63, 0x65);
+        data.swap(0x64, 0x66);
+        fs::write(&script, data).unwrap();
+        let err = verify_build(&script, &table, gamedef).unwrap_err();
+        assert_eq!(err.to_string(), "1 line(s) do not match the expected text");
+        fs::remove_file(&script).unwrap();
+        fs::remove_file(&table).unwrap();
+    }
+
+    #[test]
+    fn verify_msb_build() {
+        let gamedef = gamedef::get_by_alias("rn").unwrap();
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/rn/sample.msb");
+        let table = std::env::temp_dir().join("sc3tools_verify_msb_build.tsv");
+        let strings = extract::extract_strings(&fs::read(&input).unwrap(), gamedef).unwrap();
+        let rows: String = strings
+            .iter()
+            .map(|s| format!("{:X}\t{}\n", s.offset, s.to_markup()))
+            .collect();
+        fs::write(&table, rows).unwrap();
+        let message = verify_build(&input, &table, gamedef).unwrap();
+        assert_eq!(message, format!("All {} lines match.", strings.len()));
+        fs::remove_file(&table).unwrap();
+    }
+
+    #[test]
+    fn game_pack_lookup() {
+        let dir = std::env::temp_dir().join("sc3toolspack");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("charset.utf8"), " Tutr\n").unwrap();
+        fs::write(dir.join("compound_chars.map"), "").unwrap();
+        let packs = [GameDef::from_dir(&dir).unwrap()];
+        fs::remove_dir_all(&dir).unwrap();
+
+        let def = resolve_game(&packs, "game", "sc3toolspack").unwrap();
+        assert_eq!(def.source(), &gamedef::GameDefSource::Directory(dir));
+        let codes = text::encode_str(&text::Text("Tuturu".into()), def, false).unwrap();
+        assert_eq!(codes, vec![0x8001, 0x8002, 0x8003, 0x8002, 0x8004, 0x8002]);
+
+        let embedded = resolve_game(&packs, "game", "sg0").unwrap();
+        assert!(std::ptr::eq(
+            embedded,
+            gamedef::get_by_alias("sg0").unwrap()
+        ));
+        let err = resolve_game(&packs, "game", "nope")
+            .map(|_| ())
+            .unwrap_err();
+        assert!(err.contains("sc3toolspack,"));
+    }
+
+    #[test]
+    fn auto_detect_sg0_script() {
+        let sg0 = gamedef::get_by_alias("sg0").unwrap();
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sg0/sample.scx");
+        // The sample alone fits every Steins;Gate game equally well
+        assert!(matches!(
+            auto_detect_game(&input)
+                .map(|_| ())
+                .unwrap_err()
+                .downcast_ref(),
+            Some(ProcessingError::GameNotDetected(_))
+        ));
+
+        // Glyphs only the Steins;Gate 0 charset has
+        let rare: String = sg0.charset()[6000..6004].iter().collect();
+        let table = std::env::temp_dir().join("sc3tools_auto_detect.tsv");
+        let output = std::env::temp_dir().join("sc3tools_auto_detect.scx");
+        fs::write(&table, format!("1C\tTuturu {}\n", rare)).unwrap();
+        reinsert(&input, &table, &output, sg0, false).unwrap();
+        let (def, confidence) = auto_detect_game(&output).unwrap();
+        assert!(std::ptr::eq(def, sg0));
+        assert_eq!(confidence, 1.0);
+        fs::remove_file(&table).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn build_charset_roundtrip() {
+        let glyphs = gamedef::get_by_alias("sg0").unwrap().charset();
+        let json = format!(
+            "[{}]",
+            glyphs
+                .iter()
+                .map(|ch| match ch {
+                    '\0' => "null".to_string(),
+                    ch => json::quote(&ch.to_string()),
+                })
+                .join(",\n")
+        );
+        let csv: String = std::iter::once("index,glyph\n".to_string())
+            .chain(
+                glyphs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, ch)| **ch != '\0')
+                    .map(|(i, ch)| format!("{},{}\n", i, ch)),
+            )
+            .collect();
+
+        let from_json = json::parse_charset(&json).unwrap();
+        assert_eq!(from_json, glyphs);
+        let from_csv = parse_charset_csv(&csv).unwrap();
+        assert_eq!(from_csv, from_json[..from_csv.len()]);
+
+        let dir = std::env::temp_dir().join("sc3toolscharset");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("charset.utf8"), gamedef::build_charset(&from_json)).unwrap();
+        fs::write(dir.join("compound_chars.map"), "").unwrap();
+        let rebuilt = GameDef::from_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rebuilt.charset(), glyphs);
+
+        assert_eq!(parse_charset_csv("0,a\n1;b").unwrap_err().0, 1);
+    }
+
+    #[test]
+    fn qualified_pack_lookup() {
+        let root = std::env::temp_dir().join("sc3toolspacks");
+        let load = |pack: &str, charset: &str| {
+            let dir = root.join(pack).join("sg0");
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("charset.utf8"), charset).unwrap();
+            fs::write(dir.join("compound_chars.map"), "").unwrap();
+            GameDef::from_dir(&dir).unwrap()
+        };
+        let packs = [load("fanfix", " Tutr\n"), load("cn", " rutT\n")];
+        fs::remove_dir_all(&root).unwrap();
+
+        let fanfix = resolve_game(&packs, "game", "fanfix:sg0").unwrap();
+        let cn = resolve_game(&packs, "game", "cn:sg0").unwrap();
+        assert!(std::ptr::eq(fanfix, &packs[0]) && std::ptr::eq(cn, &packs[1]));
+        // Unqualified aliases resolve to the first pack defining them
+        let first = resolve_game(&packs, "game", "sg0").unwrap();
+        assert!(std::ptr::eq(first, &packs[0]));
+        let builtin = resolve_game(&packs, "game", "builtin:sg0").unwrap();
+        assert!(std::ptr::eq(builtin, gamedef::get_by_alias("sg0").unwrap()));
+
+        assert!(resolve_game(&packs, "game", "fanfix:sghd").is_err());
+        let err = resolve_game(&packs, "game", "nope:sg0")
+            .map(|_| ())
+            .unwrap_err();
+        assert!(err.contains("fanfix:sg0,cn:sg0,"));
+    }
+
+    #[test]
+    fn unencodable_report_json() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let replacements = vec![
+            (0, "Tuturu\u{1F600}".to_string()),
+            (1, "El Psy Kongroo".to_string()),
+            (2, "[color]\"Hi\"".to_string()),
+        ];
+        let path = Path::new("txt/sg00_01.scx.txt");
+        let failures = coz::validate_replacements(&replacements, gamedef);
+        let records: Vec<_> = failures
+            .iter()
+            .map(|(i, err)| Issue::new(path, *i, &replacements[*i].1, err).to_json())
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                "{\"file\":\"txt/sg00_01.scx.txt\",\"line\":1,\"column\":7,\"char\":\"\u{1F600}\",\
+                 \"codepoint\":128512,\"kind\":\"char-not-in-charset\"}",
+                "{\"file\":\"txt/sg00_01.scx.txt\",\"line\":3,\"column\":null,\"char\":null,\
+                 \"codepoint\":null,\"kind\":\"missing-attribute\"}",
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_conflicting_tables() {
+        let row = |line, offset, text: &str| TableRow {
+            line,
+            offset,
+            text: text.to_string(),
+        };
+        let table_a = || vec![row(0, 0x3B, "Tuturu!"), row(1, 0x10, "Okarin")];
+        let table_b = || vec![row(0, 0x3B, "Tutturu!"), row(1, 0x60, "El Psy Kongroo")];
+        let texts = |rows: Vec<TableRow>| -> Vec<(u32, String)> {
+            rows.into_iter().map(|row| (row.offset, row.text)).collect()
+        };
+        let expected_conflicts = vec![TableConflict {
+            offset: 0x3B,
+            line_a: 0,
+            line_b: 0,
+        }];
+
+        let (rows, conflicts) = merge_tables(table_a(), table_b(), MergeStrategy::PreferA);
+        assert_eq!(
+            texts(rows),
+            vec![
+                (0x10, "Okarin".to_string()),
+                (0x3B, "Tuturu!".to_string()),
+                (0x60, "El Psy Kongroo".to_string()),
+            ]
+        );
+        assert_eq!(conflicts, expected_conflicts);
+
+        let (rows, conflicts) = merge_tables(table_a(), table_b(), MergeStrategy::PreferB);
+        assert_eq!(texts(rows)[1], (0x3B, "Tutturu!".to_string()));
+        assert_eq!(conflicts, expected_conflicts);
+
+        let dir = std::env::temp_dir();
+        let (a, b, out) = (
+            dir.join("sc3tools_merge_a.tsv"),
+            dir.join("sc3tools_merge_b.tsv"),
+            dir.join("sc3tools_merge_out.tsv"),
+        );
+        fs::write(&a, "0x3B\tTuturu!\n0x10\tOkarin\n").unwrap();
+        fs::write(&b, "0x3B\tTutturu!\n").unwrap();
+        let res = run_merge_tables(&a, &b, &out, MergeStrategy::Error);
+        assert!(res.unwrap_err().to_string().starts_with("1 offset(s)"));
+        assert!(!out.exists());
+        run_merge_tables(&a, &b, &out, MergeStrategy::PreferB).unwrap();
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "0x10\tOkarin\n0x3B\tTutturu!\n"
+        );
+        for path in &[a, b, out] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn json_progress_records() {
+        let mut sink = Vec::new();
+        let mut progress = Progress::new(&mut sink, ProgressFormat::Json, true, 3);
+        for name in &["a.scx", "b.scx", "c.scx"] {
+            let path = Path::new(name);
+            progress.start(path);
+            progress.finish(path, Ok(String::new())).unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(sink).unwrap(),
+            "{\"processed\":1,\"total\":3,\"current_file\":\"a.scx\"}\n\
+             {\"processed\":2,\"total\":3,\"current_file\":\"b.scx\"}\n\
+             {\"processed\":3,\"total\":3,\"current_file\":\"c.scx\"}\n"
+        );
+    }
+}
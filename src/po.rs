@@ -0,0 +1,202 @@
+//! Exchange of script strings with translation memory tools through gettext PO files.
+//!
+//! Every string becomes an entry with its markup as the `msgid`, referenced by its offset
+//! in the script (`#: 0x1C`).
+
+use crate::extract;
+use crate::format;
+use crate::gamedef::GameDef;
+use crate::sc3::Sc3String;
+
+use std::fmt::Write;
+use std::{borrow::Cow, error, fmt};
+
+#[derive(Debug)]
+pub enum Error {
+    Extraction(extract::Error),
+    /// A line that does not belong to any entry, or an entry without a `msgstr`.
+    Malformed(usize),
+}
+
+impl error::Error for Error {}
+
+/// Exports the strings of a script as PO entries with empty translations.
+pub fn export_po(script: &[u8], gamedef: &GameDef) -> Result<String, Error> {
+    let index = format::read_string_index(script).map_err(extract::Error::from)?;
+    let mut buf = String::new();
+    writeln!(buf, "msgid \"\"").unwrap();
+    writeln!(buf, "msgstr \"Content-Type: text/plain; charset=UTF-8\\n\"").unwrap();
+    for (i, handle) in index.iter().enumerate() {
+        let data = handle.slice(script).map_err(extract::Error::from)?;
+        let s = Sc3String(Cow::from(data));
+        let markup = s
+            .serialize(gamedef, false)
+            .map_err(|err| extract::Error::String(i, err))?;
+        writeln!(buf).unwrap();
+        writeln!(buf, "#: {:#X}", handle.0.start).unwrap();
+        writeln!(buf, "msgid \"{}\"", escape(&markup.0)).unwrap();
+        writeln!(buf, "msgstr \"\"").unwrap();
+    }
+
+    Ok(buf)
+}
+
+/// Reads the translated entries of a PO file back, returning the offset and translation of each.
+/// Entries without a translation or an offset reference, such as the header, are skipped.
+pub fn import_po(po: &str) -> Result<Vec<(u32, String)>, Error> {
+    #[derive(PartialEq)]
+    enum Field {
+        None,
+        Id,
+        Str,
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = None;
+    let mut msgstr = String::new();
+    let mut field = Field::None;
+    let mut finish = |offset: &mut Option<u32>, msgstr: &mut String| {
+        if let Some(offset) = offset.take() {
+            if !msgstr.is_empty() {
+                entries.push((offset, std::mem::take(msgstr)));
+            }
+        }
+        msgstr.clear();
+    };
+
+    for (i, line) in po.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            if field != Field::Str && offset.is_some() {
+                return Err(Error::Malformed(i + 1));
+            }
+            finish(&mut offset, &mut msgstr);
+            field = Field::None;
+        } else if let Some(reference) = line.strip_prefix("#:") {
+            let reference = reference.trim().trim_start_matches("0x");
+            let parsed = u32::from_str_radix(reference, 16).map_err(|_| Error::Malformed(i + 1))?;
+            offset = Some(parsed);
+        } else if line.starts_with('#') {
+            continue;
+        } else if let Some(s) = line.strip_prefix("msgid ") {
+            unquote(s).ok_or(Error::Malformed(i + 1))?;
+            field = Field::Id;
+        } else if let Some(s) = line.strip_prefix("msgstr ") {
+            msgstr = unquote(s).ok_or(Error::Malformed(i + 1))?;
+            field = Field::Str;
+        } else if line.starts_with('"') && field != Field::None {
+            let s = unquote(line).ok_or(Error::Malformed(i + 1))?;
+            if field == Field::Str {
+                msgstr.push_str(&s);
+            }
+        } else {
+            return Err(Error::Malformed(i + 1));
+        }
+    }
+    finish(&mut offset, &mut msgstr);
+
+    Ok(entries)
+}
+
+fn escape(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\t' => buf.push_str("\\t"),
+            ch => buf.push(ch),
+        }
+    }
+    buf
+}
+
+/// Strips the quotes off a PO string literal and resolves its escape sequences.
+fn unquote(s: &str) -> Option<String> {
+    let s = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut buf = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            buf.push(ch);
+            continue;
+        }
+        match chars.next()? {
+            'n' => buf.push('\n'),
+            't' => buf.push('\t'),
+            ch @ '"' | ch @ '\\' => buf.push(ch),
+            _ => return None,
+        }
+    }
+    Some(buf)
+}
+
+impl From<extract::Error> for Error {
+    fn from(err: extract::Error) -> Self {
+        Error::Extraction(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Extraction(err) => fmt::Display::fmt(err, f),
+            Error::Malformed(line) => write!(f, "malformed PO entry at line {}", line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::load_fixture;
+    use crate::gamedef;
+
+    #[test]
+    fn po_roundtrip() {
+        let gamedef = gamedef::get_by_alias("sg0").unwrap();
+        let script = load_fixture("sg0", "sample.scx");
+        let po = export_po(&script, gamedef).unwrap();
+        assert!(po.contains("#: 0x3B\nmsgid \"[ruby-base]鳳凰院凶真"));
+        // Nothing has been translated yet
+        assert!(import_po(&po).unwrap().is_empty());
+
+        // Fill in each translation with the source text
+        let mut translated = String::new();
+        let mut msgid = "";
+        for line in po.lines() {
+            if let Some(s) = line.strip_prefix("msgid ") {
+                msgid = s;
+            }
+            match line {
+                "msgstr \"\"" => writeln!(translated, "msgstr {}", msgid).unwrap(),
+                line => writeln!(translated, "{}", line).unwrap(),
+            }
+        }
+
+        let entries = import_po(&translated).unwrap();
+        let strings = extract::extract_strings(&script, gamedef).unwrap();
+        assert_eq!(entries.len(), strings.len());
+        let index = format::read_string_index(&script).unwrap();
+        for ((offset, text), handle) in entries.iter().zip(index.iter()) {
+            assert_eq!(*offset, handle.0.start);
+            let s = Sc3String(Cow::from(handle.slice(&script).unwrap()));
+            assert_eq!(text, &s.serialize(gamedef, false).unwrap().0);
+        }
+    }
+
+    #[test]
+    fn po_escapes() {
+        let s = "Say \"Tuturu\"\\\n";
+        assert_eq!(unquote(&format!("\"{}\"", escape(s))).unwrap(), s);
+        assert_eq!(
+            import_po("#: 0x10\nmsgid \"a\"\nmsgstr \"\"\n\"b\\\"\"\n\"c\"\n").unwrap(),
+            vec![(0x10, "b\"c".to_string())]
+        );
+        assert!(matches!(
+            import_po("#: 0x10\nmsgid \"a\"\n\n"),
+            Err(Error::Malformed(3))
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::gamedef::GameDef;
+use crate::text::{self, EncodingError, Text};
 use byteorder::{BigEndian, WriteBytesExt};
 use nom::{
     bytes::complete::{tag, take},
@@ -18,17 +20,108 @@ pub enum Error {
 
 impl std::error::Error for Error {}
 
+/// The opcode ending each string, unless the script layout declares another one.
+pub const DEFAULT_TERMINATOR: u8 = 0xFF;
+
+/// What character codes are offset from, unless the script layout declares another base.
+pub const DEFAULT_CHAR_CODE_BASE: u16 = 0x8000;
+
+/// How strings end, unless the script layout declares otherwise.
+pub const DEFAULT_FRAMING: Framing = Framing {
+    terminator: DEFAULT_TERMINATOR,
+    escape: None,
+};
+
+/// How the strings of a script end, and how character codes that would read as something else
+/// are told apart. See `ScriptLayout`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Framing {
+    /// Opcode ending each string.
+    pub terminator: u8,
+    /// Byte marking the character code after it as text, for codes whose first byte is the
+    /// terminator, an opcode or the escape byte itself. Without one, such codes can't be stored.
+    pub escape: Option<u8>,
+}
+
+impl Framing {
+    /// Whether `b`, the first byte of a token, starts something other than text.
+    fn ends_text(&self, b: u8) -> bool {
+        (b < 0x80u8 || b == self.terminator) && Some(b) != self.escape
+    }
+
+    /// Returns the escape byte to put before `code`, if it wouldn't be read back as text
+    /// otherwise.
+    fn escape_for(&self, code: u16) -> Option<u8> {
+        let [lead, _] = code.to_be_bytes();
+        self.escape
+            .filter(|escape| self.ends_text(lead) || lead == *escape)
+    }
+}
+
 #[derive(Clone)]
 pub struct Sc3String<'a>(pub Cow<'a, [u8]>);
 
 impl<'a> Sc3String<'_> {
     pub fn iter(&self) -> Sc3StringIter {
-        Sc3StringIter { remaining: &self.0 }
+        self.iter_with(DEFAULT_FRAMING)
+    }
+
+    /// Like `iter`, but for strings framed otherwise. See `GameDef::string_framing`.
+    pub fn iter_with(&self, framing: Framing) -> Sc3StringIter<'_> {
+        Sc3StringIter {
+            remaining: &self.0,
+            framing,
+        }
+    }
+}
+
+/// Encodes plain text into a complete string, terminator included.
+pub fn encode_with_terminator(
+    text: &Text,
+    gamedef: &GameDef,
+    convert_to_fullwidth: bool,
+) -> Result<Sc3String<'static>, EncodingError> {
+    let codes = text::encode_str(text, gamedef, convert_to_fullwidth)?;
+    let framing = gamedef.string_framing();
+    let mut buf = Vec::new();
+    StringToken::Text(codes.into())
+        .encode_with(&mut buf, framing)
+        .unwrap();
+    StringToken::Terminator
+        .encode_with(&mut buf, framing)
+        .unwrap();
+    Ok(Sc3String(buf.into()))
+}
+
+/// Returns `replacement` followed by the control tokens that end `original`, such as a color
+/// reset, provided that `replacement` is plain text. Otherwise, or if `original` ends in text,
+/// `replacement` is returned unchanged. Both strings are framed as `framing` says.
+pub fn inherit_trailing_controls(
+    replacement: &Sc3String,
+    original: &Sc3String,
+    framing: Framing,
+) -> Result<Sc3String<'static>, Error> {
+    let tokens = replacement
+        .iter_with(framing)
+        .collect::<Result<Vec<_>, _>>()?;
+    let original_tokens = original.iter_with(framing).collect::<Result<Vec<_>, _>>()?;
+    let is_text = |tk: &StringToken| matches!(tk, StringToken::Text(_));
+    let trailing = match original_tokens.iter().rposition(is_text) {
+        Some(last_text) if tokens.iter().all(is_text) => &original_tokens[last_text + 1..],
+        _ => &[],
+    };
+
+    let mut buf = Vec::with_capacity(replacement.0.len());
+    for tk in tokens.iter().chain(trailing) {
+        tk.encode_with(&mut buf, framing)?;
     }
+    StringToken::Terminator.encode_with(&mut buf, framing)?;
+    Ok(Sc3String(buf.into()))
 }
 
 pub struct Sc3StringIter<'a> {
     remaining: &'a [u8],
+    framing: Framing,
 }
 
 impl<'a> Iterator for Sc3StringIter<'a> {
@@ -38,7 +131,7 @@ impl<'a> Iterator for Sc3StringIter<'a> {
         if self.remaining.is_empty() {
             return None;
         }
-        match StringToken::decode(&self.remaining) {
+        match StringToken::decode_with(self.remaining, self.framing) {
             Ok((rem, tk)) => {
                 self.remaining = rem;
                 if let StringToken::Terminator = tk {
@@ -88,6 +181,10 @@ pub enum PresentAction {
     Unknown_0x18,
 }
 
+/// The opcode that introduces a control token (anything but text) within a string.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ControlCode(pub u8);
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Expr<'a>(pub Cow<'a, [u8]>);
 
@@ -119,6 +216,12 @@ impl<'a> Expr<'a> {
 
 impl<'a> StringToken<'_> {
     pub fn decode(i: &[u8]) -> Result<(&[u8], StringToken), Error> {
+        Self::decode_with(i, DEFAULT_FRAMING)
+    }
+
+    /// Like `decode`, but for strings framed otherwise. The terminator takes precedence over any
+    /// opcode or unescaped character code it collides with.
+    pub fn decode_with(i: &[u8], framing: Framing) -> Result<(&[u8], StringToken<'_>), Error> {
         fn parse<'a, O, P, F>(i: &'a [u8], parser: P, f: F) -> Result<(&[u8], StringToken), Error>
         where
             P: Fn(&'a [u8]) -> IResult<&'a [u8], O>,
@@ -128,20 +231,32 @@ impl<'a> StringToken<'_> {
             Ok((i, f(val)))
         }
 
-        fn peek_op(i: &[u8]) -> IResult<&[u8], u8> {
+        fn peek_op(i: &[u8], framing: Framing) -> IResult<&[u8], u8> {
             let (_, b) = peek(be_u8)(i)?;
-            let (i, _) = cond(b < 0x80u8 || b == 0xFFu8, take(1usize))(i)?;
+            let (i, _) = cond(framing.ends_text(b), take(1usize))(i)?;
             Ok((i, b))
         }
 
-        fn text(i: &[u8]) -> IResult<&[u8], Vec<u16>> {
+        fn text<'a>(i: &'a [u8], framing: Framing) -> IResult<&'a [u8], Vec<u16>> {
+            let code = |i: &'a [u8]| match i {
+                [b, rest @ ..] if Some(*b) == framing.escape => be_u16(rest),
+                _ => be_u16(i),
+            };
             let (i, (chars, _)) =
-                many_till(be_u16, verify(peek(be_u8), |b| *b < 0x80u8 || *b == 0xFFu8))(i)?;
+                many_till(code, verify(peek(be_u8), |b| framing.ends_text(*b)))(i)?;
             Ok((i, chars))
         }
 
-        let (i, op) = peek_op(i).map_err(|_| Error::ExpectedMoreInput)?;
+        let (i, op) = peek_op(i, framing).map_err(|_| Error::ExpectedMoreInput)?;
+        if op == framing.terminator {
+            return Ok((i, StringToken::Terminator));
+        }
         match op {
+            _ if !framing.ends_text(op) => parse(
+                i,
+                |i| text(i, framing),
+                |chars| StringToken::Text(chars.into()),
+            ),
             0x00 => Ok((i, StringToken::LineBreak)),
             0x01 => Ok((i, StringToken::NameStart)),
             0x02 => Ok((i, StringToken::LineStart)),
@@ -164,21 +279,39 @@ impl<'a> StringToken<'_> {
             0x1A => Ok((i, StringToken::AutoForward_1A)),
             0x1E => Ok((i, StringToken::RubyCenterPerChar)),
             0x1F => Ok((i, StringToken::AltLineBreak)),
-            0xFF => Ok((i, StringToken::Terminator)),
-            #[allow(overlapping_patterns)]
-            0x00..=0x7F => Err(Error::UnrecognizedInstr(op)),
-            _ => parse(i, text, |chars| StringToken::Text(chars.into())),
+            _ => Err(Error::UnrecognizedInstr(op)),
         }
     }
 
-    pub fn encode(&self, sink: &mut impl io::Write) -> Result<(), io::Error> {
-        if let StringToken::Text(chars) = self {
-            for code in chars.iter() {
-                sink.write_u16::<BigEndian>(*code)?;
-            }
-            return Ok(());
+    /// Copies whatever the token borrows, so that it outlives the data it was parsed from.
+    pub fn into_owned(self) -> StringToken<'static> {
+        match self {
+            StringToken::Text(chars) => StringToken::Text(Cow::Owned(chars.into_owned())),
+            StringToken::LineBreak => StringToken::LineBreak,
+            StringToken::NameStart => StringToken::NameStart,
+            StringToken::LineStart => StringToken::LineStart,
+            StringToken::Present(action) => StringToken::Present(action),
+            StringToken::Color(expr) => StringToken::Color(Expr(Cow::Owned(expr.0.into_owned()))),
+            StringToken::RubyBaseStart => StringToken::RubyBaseStart,
+            StringToken::RubyTextStart => StringToken::RubyTextStart,
+            StringToken::RubyTextEnd => StringToken::RubyTextEnd,
+            StringToken::FontSize(val) => StringToken::FontSize(val),
+            StringToken::Parallel => StringToken::Parallel,
+            StringToken::Center => StringToken::Center,
+            StringToken::MarginTop(val) => StringToken::MarginTop(val),
+            StringToken::MarginLeft(val) => StringToken::MarginLeft(val),
+            StringToken::HardcodedValue(val) => StringToken::HardcodedValue(val),
+            StringToken::Eval(expr) => StringToken::Eval(Expr(Cow::Owned(expr.0.into_owned()))),
+            StringToken::AutoForward => StringToken::AutoForward,
+            StringToken::AutoForward_1A => StringToken::AutoForward_1A,
+            StringToken::RubyCenterPerChar => StringToken::RubyCenterPerChar,
+            StringToken::AltLineBreak => StringToken::AltLineBreak,
+            StringToken::Terminator => StringToken::Terminator,
         }
+    }
 
+    /// Returns the opcode of a control token, or `None` for text.
+    pub fn control_code(&self) -> Option<ControlCode> {
         let code: u8 = match self {
             StringToken::LineBreak => 0x00,
             StringToken::NameStart => 0x01,
@@ -202,8 +335,31 @@ impl<'a> StringToken<'_> {
             StringToken::AutoForward_1A => 0x1A,
             StringToken::RubyCenterPerChar => 0x1E,
             StringToken::AltLineBreak => 0x1F,
-            StringToken::Terminator => 0xFF,
-            StringToken::Text(_) => unreachable!(),
+            StringToken::Terminator => DEFAULT_TERMINATOR,
+            StringToken::Text(_) => return None,
+        };
+        Some(ControlCode(code))
+    }
+
+    /// Writes the token out as it is stored in strings framed as `framing` says.
+    /// See `GameDef::string_framing`.
+    pub fn encode_with(
+        &self,
+        sink: &mut impl io::Write,
+        framing: Framing,
+    ) -> Result<(), io::Error> {
+        let code = match self {
+            StringToken::Text(chars) => {
+                for code in chars.iter() {
+                    if let Some(escape) = framing.escape_for(*code) {
+                        sink.write_u8(escape)?;
+                    }
+                    sink.write_u16::<BigEndian>(*code)?;
+                }
+                return Ok(());
+            }
+            StringToken::Terminator => framing.terminator,
+            tk => tk.control_code().unwrap().0,
         };
 
         sink.write(&code.to_be_bytes()).map(|_| ())?;
@@ -221,6 +377,12 @@ impl<'a> StringToken<'_> {
     }
 }
 
+impl fmt::Display for ControlCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04X}", self.0)
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Error {
         Error::Io(error)
@@ -254,4 +416,93 @@ mod tests {
         let expr = vec![0x29, 0x0A, 0xA0, 0x5A, 0x14, 0x14, 0x00, 0x80, 0x00, 0x00];
         assert_eq!(Expr::parse(&expr).unwrap().1, Expr(Cow::from(&expr)));
     }
+
+    #[test]
+    fn terminated_encode_roundtrip() {
+        let gamedef = crate::gamedef::get(crate::gamedef::Game::SteinsGate0);
+        let text = Text(Cow::from("Tuturu"));
+        let mut s = encode_with_terminator(&text, gamedef, false).unwrap();
+        assert_eq!(s.0.last(), Some(&0xFF));
+
+        // Anything past the terminator belongs to the next string.
+        s.0.to_mut().extend_from_slice(&[0x80, 0x01]);
+        let tokens = s.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(tokens.len(), 1);
+        if let StringToken::Text(codes) = &tokens[0] {
+            let decoded = text::decode_str(codes, gamedef, false).unwrap();
+            assert_eq!(decoded, text);
+        } else {
+            panic!("expected a text token, got {:?}", tokens[0]);
+        }
+    }
+
+    #[test]
+    fn layout_terminator_roundtrip() {
+        use crate::coz::CozString;
+        use crate::format::{Format, ScriptLayout, Scx};
+
+        let gamedef = crate::gamedef::test_def(" Tur", "").with_script_layout(ScriptLayout {
+            terminator: 0xFE,
+            ..Scx::layout()
+        });
+        let markup = CozString(Cow::from("Tu[linebreak]rur"));
+        let s = Sc3String::deserialize(&markup, &gamedef, false).unwrap();
+        assert_eq!(s.0.last(), Some(&0xFE));
+        assert!(!s.0.contains(&0xFF));
+        assert_eq!(s.serialize(&gamedef, false).unwrap(), markup);
+
+        let s = encode_with_terminator(&Text(Cow::from("Tu")), &gamedef, false).unwrap();
+        assert_eq!(&s.0[..], &[0x80, 0x01, 0x80, 0x02, 0xFE]);
+    }
+
+    #[test]
+    fn escaped_char_codes() {
+        let framing = Framing {
+            terminator: 0xFE,
+            escape: Some(0xFD),
+        };
+        // Codes starting with the terminator or the escape byte are escaped, others aren't
+        let tokens = [
+            StringToken::Text(vec![0x8001, 0xFE02, 0xFD03].into()),
+            StringToken::Terminator,
+        ];
+        let mut buf = Vec::new();
+        for tk in &tokens {
+            tk.encode_with(&mut buf, framing).unwrap();
+        }
+        assert_eq!(buf, [0x80, 0x01, 0xFD, 0xFE, 0x02, 0xFD, 0xFD, 0x03, 0xFE]);
+
+        let s = Sc3String(Cow::from(buf));
+        let decoded = s.iter_with(framing).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(decoded, tokens[..1]);
+        // Without the escape byte, the code reads as the end of the string
+        let s = Sc3String(Cow::from(&[0x80, 0x01, 0xFE, 0x02][..]));
+        assert_eq!(s.iter_with(framing).count(), 1);
+    }
+
+    #[test]
+    fn inherit_color_reset() {
+        use crate::coz::CozString;
+
+        let gamedef = crate::gamedef::get(crate::gamedef::Game::SteinsGate0);
+        let deserialize = |markup: &str| {
+            let markup = CozString(Cow::from(markup));
+            let s = Sc3String::deserialize(&markup, gamedef, false).unwrap();
+            Sc3String(Cow::from(s.0.into_owned()))
+        };
+        let original = deserialize("[color index=\"0A0000\"]Tuturu[color index=\"00\"]");
+        let translated =
+            inherit_trailing_controls(&deserialize("Mayushii"), &original, DEFAULT_FRAMING)
+                .unwrap();
+        assert_eq!(
+            translated.serialize(gamedef, false).unwrap().0,
+            "Mayushii[color index=\"00\"]"
+        );
+
+        // Markup in the replacement is left as is
+        let replacement = deserialize("[color index=\"0A0000\"]Mayushii");
+        let translated =
+            inherit_trailing_controls(&replacement, &original, DEFAULT_FRAMING).unwrap();
+        assert_eq!(translated.0, replacement.0);
+    }
 }
@@ -0,0 +1,134 @@
+//! Reading of scenes authored as plain text, one `SPEAKER: line` block per string.
+//!
+//! Blocks are separated by blank lines, and lines within a block are joined with a space, as
+//! in Markdown paragraphs. A block starts with a speaker header (`Okabe: Tuturu`) unless it is
+//! narration. Narration that itself contains a colon can be written with an empty speaker
+//! (`: 3:00 PM`). Lines starting with `#` are comments.
+
+use crate::coz::{self, CozString};
+use crate::gamedef::GameDef;
+use crate::sc3::Sc3String;
+
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourceLine {
+    /// Line number the block starts at, starting from 1.
+    pub line: usize,
+    pub speaker: Option<String>,
+    /// Markup of the spoken text or narration.
+    pub body: String,
+}
+
+impl SourceLine {
+    /// Returns the markup of the whole string, with the speaker in a name tag.
+    pub fn to_markup(&self) -> String {
+        match &self.speaker {
+            Some(speaker) => format!("[name]{}[line]{}", speaker, self.body),
+            None => self.body.clone(),
+        }
+    }
+
+    /// Encodes the string the way `Sc3String::deserialize` encodes its markup.
+    pub fn encode(
+        &self,
+        gamedef: &GameDef,
+        convert_to_fullwidth: bool,
+    ) -> Result<Sc3String<'static>, coz::Error> {
+        let markup = CozString(Cow::from(self.to_markup()));
+        let s = Sc3String::deserialize(&markup, gamedef, convert_to_fullwidth)?;
+        Ok(Sc3String(Cow::from(s.0.into_owned())))
+    }
+}
+
+/// Splits a scene into the strings it consists of.
+pub fn parse_script_source(text: &str) -> Vec<SourceLine> {
+    let mut lines = Vec::new();
+    let mut current: Option<SourceLine> = None;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            lines.extend(current.take());
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        match current.as_mut() {
+            Some(block) => {
+                block.body.push(' ');
+                block.body.push_str(line);
+            }
+            None => {
+                let (speaker, body) = split_speaker(line);
+                current = Some(SourceLine {
+                    line: i + 1,
+                    speaker: speaker.map(str::to_string),
+                    body: body.to_string(),
+                });
+            }
+        }
+    }
+    lines.extend(current);
+
+    lines
+}
+
+/// Splits a speaker header off the first line of a block. Text before the first `": "` is only
+/// taken as a speaker if it has no markup or sentence punctuation, so that narration such as
+/// `Wait. Listen: ...` is left alone.
+fn split_speaker(line: &str) -> (Option<&str>, &str) {
+    let pos = match line.find(':') {
+        Some(pos) => pos,
+        None => return (None, line),
+    };
+    let (speaker, rest) = (line[..pos].trim(), &line[pos + 1..]);
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return (None, line);
+    }
+    if speaker.is_empty() {
+        return (None, rest.trim_start());
+    }
+    if speaker.contains(|c| "[].!?\"".contains(c)) {
+        return (None, line);
+    }
+    (Some(speaker), rest.trim_start())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamedef;
+
+    #[test]
+    fn two_speaker_exchange() {
+        let src = "# Lab, evening\n\
+                   Mayuri: Tuturu!\n\
+                   \n\
+                   Okabe: It is I,\n\
+                   Hououin Kyouma!\n\
+                   \n\
+                   Wait. Listen: it's late.\n\
+                   \n\
+                   : 3:00 PM\n";
+        let lines = parse_script_source(src);
+        let expected = [
+            (2, Some("Mayuri"), "Tuturu!"),
+            (4, Some("Okabe"), "It is I, Hououin Kyouma!"),
+            (7, None, "Wait. Listen: it's late."),
+            (9, None, "3:00 PM"),
+        ];
+        assert_eq!(lines.len(), expected.len());
+        for (line, (n, speaker, body)) in lines.iter().zip(expected.iter()) {
+            assert_eq!(line.line, *n);
+            assert_eq!(line.speaker.as_deref(), *speaker);
+            assert_eq!(line.body, *body);
+        }
+
+        assert_eq!(lines[0].to_markup(), "[name]Mayuri[line]Tuturu!");
+        let gamedef = gamedef::get_by_alias("sg0").unwrap();
+        let markup = CozString(Cow::from("[name]Mayuri[line]Tuturu!"));
+        let expected = Sc3String::deserialize(&markup, gamedef, false).unwrap();
+        assert_eq!(lines[0].encode(gamedef, false).unwrap().0, expected.0);
+    }
+}
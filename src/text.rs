@@ -3,10 +3,108 @@ use nom::{
     combinator::map, combinator::map_res, combinator::recognize, sequence::delimited, IResult,
 };
 
+use crate::compose;
 use crate::gamedef::GameDef;
-use std::{borrow::Cow, collections::HashMap, error, fmt};
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    error, fmt,
+};
 
 pub const FULLWIDTH_SPACE: char = '\u{3000}';
+/// A wrap hint, which is dropped on encoding. See `layout::wrap_to_width`.
+pub const SOFT_HYPHEN: char = '\u{ad}';
+
+/// Tab width used when tab expansion is requested without specifying one.
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// Normalizations applied to source text before it is encoded. All of them are off by default.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct EncodeOptions {
+    /// Expands tabs to spaces up to the next multiple of the given width. SC3 has no tab glyph.
+    pub tab_width: Option<usize>,
+    /// Which surrounding whitespace to drop.
+    pub trim: TrimPolicy,
+    /// Recomposes combining diacritics into precomposed characters, which are all the fonts
+    /// have. See `compose::recompose`.
+    pub recompose: bool,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TrimPolicy {
+    None,
+    Trailing,
+    Both,
+}
+
+impl Default for TrimPolicy {
+    fn default() -> Self {
+        TrimPolicy::None
+    }
+}
+
+/// Which form of characters that exist in both halfwidth and fullwidth to write text in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WidthForm {
+    Halfwidth,
+    Fullwidth,
+}
+
+/// Placeholder suggested for rendering padding slots. See `DecodeOptions`.
+pub const DEFAULT_PADDING_PLACEHOLDER: char = '·';
+
+/// Options for decoding. The defaults decode strictly, as `decode_str` does.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct DecodeOptions {
+    /// Renders character codes referring to padding slots of the charset as the given character,
+    /// making them visible when debugging a script.
+    pub padding_placeholder: Option<char>,
+    /// Decodes Private Use Area glyphs that the compound map has no expansion for as the glyphs
+    /// themselves, instead of failing with `EncodingError::PuaCharNotMapped`.
+    pub literal_unmapped_pua: bool,
+}
+
+impl EncodeOptions {
+    pub fn normalize<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let s = match self.trim {
+            TrimPolicy::None => s,
+            TrimPolicy::Trailing => s.trim_end(),
+            TrimPolicy::Both => s.trim(),
+        };
+        let s = match self.tab_width {
+            Some(width) if s.contains('\t') => Cow::Owned(expand_tabs(s, width)),
+            _ => Cow::Borrowed(s),
+        };
+        if self.recompose {
+            Cow::Owned(compose::recompose(&s).0)
+        } else {
+            s
+        }
+    }
+}
+
+fn expand_tabs(s: &str, width: usize) -> String {
+    let mut buf = String::with_capacity(s.len());
+    let mut column = 0;
+    for ch in s.chars() {
+        match ch {
+            '\t' => {
+                let n = width - column % width.max(1);
+                buf.push_str(&" ".repeat(n));
+                column += n;
+            }
+            '\n' => {
+                buf.push(ch);
+                column = 0;
+            }
+            _ => {
+                buf.push(ch);
+                column += 1;
+            }
+        }
+    }
+    buf
+}
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Text<'a>(pub Cow<'a, str>);
@@ -35,26 +133,30 @@ impl<'a> Text<'a> {
     ) -> Text<'a> {
         let mut buf = String::new();
         for res in chars {
-            match res {
-                Char::Regular(mut c) => {
-                    if !keep_fullwidth_chars {
-                        c = replace_fullwidth(c);
-                    }
-
-                    buf.push(c);
-                }
-                Char::Compound(s) => {
-                    buf.push('[');
-                    buf.push_str(&s);
-                    buf.push(']');
-                }
-            }
+            push_char(&mut buf, res, keep_fullwidth_chars);
         }
 
         Text(buf.into())
     }
 }
 
+fn push_char(buf: &mut String, ch: Char, keep_fullwidth_chars: bool) {
+    match ch {
+        Char::Regular(mut c) => {
+            if !keep_fullwidth_chars {
+                c = replace_fullwidth(c);
+            }
+
+            buf.push(c);
+        }
+        Char::Compound(s) => {
+            buf.push('[');
+            buf.push_str(s);
+            buf.push(']');
+        }
+    }
+}
+
 pub struct CharIterator<'a> {
     remaining: &'a str,
     encoding_maps: &'a EncodingMaps,
@@ -65,7 +167,7 @@ impl<'a> Iterator for CharIterator<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         fn next_char<'a>(s: &'a str, encoding_maps: &EncodingMaps) -> IResult<&'a str, Char<'a>> {
-            let encode_compound = move |ch| encode_compound_char(ch, &encoding_maps);
+            let encode_compound = move |ch| compound_char_index(ch, &encoding_maps);
             let compound = delimited(
                 char('['),
                 recognize(map_res(is_not("]"), encode_compound)),
@@ -90,15 +192,34 @@ pub enum EncodingError {
     IllegalCharCode(u16),
     CharNotInCharset(String),
     PuaCharNotMapped(u16, char),
+    /// A glyph the charset has, but that encoding was restricted from using.
+    CharNotAllowed(char),
+    /// The text encodes to more character codes than the record it must fit in.
+    RecordOverflow {
+        encoded: usize,
+        capacity: usize,
+    },
 }
 
 impl error::Error for EncodingError {}
 
+#[derive(Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The input ends in the middle of a character code.
+    TruncatedInput {
+        byte_len: usize,
+    },
+    Encoding(EncodingError),
+}
+
+impl error::Error for DecodeError {}
+
 #[derive(Debug)]
 pub struct EncodingMapConstructionError {
     pub missing_pua_chars: Vec<char>,
 }
 
+/// Maps characters to the charset index of their glyph.
 pub struct EncodingMaps {
     main: HashMap<char, u16>,
     compound: HashMap<String, u16>,
@@ -109,13 +230,10 @@ impl EncodingMaps {
         charset: &[char],
         pua_mappings: &HashMap<char, String>,
     ) -> Result<Self, EncodingMapConstructionError> {
-        let main: HashMap<_, _> = (0..charset.len())
-            .map(|i| {
-                let high_byte = 0x80u8 + (i / 256) as u8;
-                let low_byte = (i % 256) as u8;
-                let code = (high_byte as u16) << 8u16 | (low_byte as u16);
-                (charset[i], code)
-            })
+        let main: HashMap<_, _> = charset
+            .iter()
+            .enumerate()
+            .map(|(i, ch)| (*ch, i as u16))
             .collect();
 
         let lookup_compound = |ch| main.get(ch).ok_or_else(|| *ch);
@@ -131,39 +249,105 @@ impl EncodingMaps {
             });
         }
 
-        let compound: HashMap<_, _> = compound.into_iter().map(Result::unwrap).collect();
-        Ok(EncodingMaps { main, compound })
+        // Several PUA characters can share an expansion; the lowest charset index wins
+        let mut lowest = HashMap::new();
+        for (expansion, code) in compound.into_iter().map(Result::unwrap) {
+            let entry = lowest.entry(expansion).or_insert(code);
+            *entry = (*entry).min(code);
+        }
+        Ok(EncodingMaps {
+            main,
+            compound: lowest,
+        })
+    }
+
+    /// Returns the charset index of a regular (non-compound) character.
+    pub fn index_of(&self, ch: char) -> Option<u16> {
+        self.main.get(&ch).cloned()
+    }
+
+    /// Returns the mapping of regular characters to charset indices, sorted by character.
+    /// Padding slots are left out.
+    pub fn forward_table(&self) -> Vec<(char, u16)> {
+        let mut table: Vec<_> = self
+            .main
+            .iter()
+            .filter(|(ch, _)| **ch != '\0')
+            .map(|(ch, code)| (*ch, *code))
+            .collect();
+        table.sort_unstable();
+        table
     }
 }
 
+/// Encodes text into character codes. Soft hyphens are dropped.
 pub fn encode_str(
     s: &Text,
     gamedef: &GameDef,
     convert_to_fullwidth: bool,
 ) -> Result<Vec<u16>, EncodingError> {
     let mut buf = Vec::new();
-    for mut ch in s.iter(&gamedef.encoding_maps) {
-        if let Char::Regular(c) = &ch {
-            if convert_to_fullwidth && !gamedef.fullwidth_blocklist.contains(&*c) {
-                ch = Char::Regular(replace_halfwidth(*c));
-            } else if *c == '\u{20}' {
-                ch = Char::Regular(FULLWIDTH_SPACE);
-            }
-        }
+    for ch in substitute(s, gamedef, convert_to_fullwidth) {
         buf.push(encode_char(&ch, &gamedef)?);
     }
 
     Ok(buf)
 }
 
-fn encode_char(ch: &Char, gamedef: &GameDef) -> Result<u16, EncodingError> {
+/// Computes the number of bytes `encode_str` would produce for `s`, without building the output.
+pub fn encoded_byte_len(
+    s: &Text,
+    gamedef: &GameDef,
+    convert_to_fullwidth: bool,
+) -> Result<usize, EncodingError> {
+    let mut len = 0;
+    for ch in substitute(s, gamedef, convert_to_fullwidth) {
+        encode_char(&ch, gamedef)?;
+        len += std::mem::size_of::<u16>();
+    }
+
+    Ok(len)
+}
+
+/// Yields the characters of `s` as `encode_str` looks them up: soft hyphens are dropped,
+/// spaces become ideographic spaces, and other halfwidth characters become fullwidth if requested.
+pub fn substitute<'a>(
+    s: &'a Text,
+    gamedef: &'a GameDef,
+    convert_to_fullwidth: bool,
+) -> impl Iterator<Item = Char<'a>> {
+    s.iter(&gamedef.encoding_maps)
+        .filter(is_encoded)
+        .map(move |ch| adjust_width(ch, gamedef, convert_to_fullwidth))
+}
+
+fn is_encoded(ch: &Char) -> bool {
+    *ch != Char::Regular(SOFT_HYPHEN)
+}
+
+fn adjust_width<'a>(ch: Char<'a>, gamedef: &GameDef, convert_to_fullwidth: bool) -> Char<'a> {
+    match ch {
+        Char::Regular(c) if convert_to_fullwidth && !gamedef.fullwidth_blocklist.contains(&c) => {
+            Char::Regular(replace_halfwidth(c))
+        }
+        Char::Regular('\u{20}') => Char::Regular(FULLWIDTH_SPACE),
+        ch => ch,
+    }
+}
+
+pub fn encode_char(ch: &Char, gamedef: &GameDef) -> Result<u16, EncodingError> {
+    char_index(ch, gamedef).map(|i| gamedef.char_code(i))
+}
+
+/// Returns the charset index of the glyph `encode_char` encodes `ch` with.
+pub fn char_index(ch: &Char, gamedef: &GameDef) -> Result<u16, EncodingError> {
     match ch {
-        Char::Compound(s) => encode_compound_char(s, &gamedef.encoding_maps),
-        Char::Regular(c) => encode_regular_char(*c, &gamedef.encoding_maps),
+        Char::Compound(s) => compound_char_index(s, &gamedef.encoding_maps),
+        Char::Regular(c) => regular_char_index(*c, &gamedef.encoding_maps),
     }
 }
 
-fn encode_regular_char(c: char, encoding_maps: &EncodingMaps) -> Result<u16, EncodingError> {
+fn regular_char_index(c: char, encoding_maps: &EncodingMaps) -> Result<u16, EncodingError> {
     encoding_maps
         .main
         .get(&c)
@@ -171,7 +355,7 @@ fn encode_regular_char(c: char, encoding_maps: &EncodingMaps) -> Result<u16, Enc
         .ok_or_else(|| EncodingError::CharNotInCharset(c.to_string()))
 }
 
-fn encode_compound_char(ch: &str, encoding_maps: &EncodingMaps) -> Result<u16, EncodingError> {
+fn compound_char_index(ch: &str, encoding_maps: &EncodingMaps) -> Result<u16, EncodingError> {
     encoding_maps
         .compound
         .get(ch)
@@ -179,40 +363,142 @@ fn encode_compound_char(ch: &str, encoding_maps: &EncodingMaps) -> Result<u16, E
         .ok_or_else(|| EncodingError::CharNotInCharset(ch.to_string()))
 }
 
+/// Decodes a raw stream of big-endian character codes.
+pub fn decode_bytes<'a>(
+    bytes: &[u8],
+    gamedef: &'a GameDef,
+    keep_fullwidth_chars: bool,
+) -> Result<Text<'a>, DecodeError> {
+    if bytes.len() % 2 != 0 {
+        return Err(DecodeError::TruncatedInput {
+            byte_len: bytes.len(),
+        });
+    }
+
+    let codes: Vec<_> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    Ok(decode_str(&codes, gamedef, keep_fullwidth_chars)?)
+}
+
 pub fn decode_str<'a>(
     s: &[u16],
     gamedef: &'a GameDef,
     keep_fullwidth_chars: bool,
 ) -> Result<Text<'a>, EncodingError> {
-    let chars = s
-        .iter()
-        .map(|code| decode_char(*code, gamedef.charset(), &gamedef.compound_chars))
-        .collect::<Result<Vec<_>, _>>()?;
-    Ok(Text::from_chars(chars.into_iter(), keep_fullwidth_chars))
+    let mut buf = String::new();
+    decode_into(s, gamedef, keep_fullwidth_chars, &mut buf)?;
+    Ok(Text(buf.into()))
 }
 
-pub fn decode_char<'a>(
+/// Decodes `s`, appending the result to `out`. On error, `out` is left unchanged.
+pub fn decode_into(
+    s: &[u16],
+    gamedef: &GameDef,
+    keep_fullwidth_chars: bool,
+    out: &mut String,
+) -> Result<(), EncodingError> {
+    decode_into_with(
+        s,
+        gamedef,
+        keep_fullwidth_chars,
+        &DecodeOptions::default(),
+        out,
+    )
+}
+
+/// Like `decode_into`, with the given `options`.
+pub fn decode_into_with(
+    s: &[u16],
+    gamedef: &GameDef,
+    keep_fullwidth_chars: bool,
+    options: &DecodeOptions,
+    out: &mut String,
+) -> Result<(), EncodingError> {
+    let len = out.len();
+    for code in s {
+        // Codes past the end of the charset still fail
+        let in_range = gamedef.glyph_of(*code).is_some();
+        match options.padding_placeholder {
+            Some(placeholder) if in_range && gamedef.is_padding(*code) => {
+                out.push(placeholder);
+                continue;
+            }
+            _ => {}
+        }
+        match decode_char_with(*code, gamedef, options) {
+            Ok(ch) => push_char(out, ch, keep_fullwidth_chars),
+            Err(err) => {
+                out.truncate(len);
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Decodes a single character code. Codes of padding slots decode to `'\0'`, leaving it to the
+/// caller to report them; see `DecodeOptions::padding_placeholder`.
+pub fn decode_char(code: u16, gamedef: &GameDef) -> Result<Char<'_>, EncodingError> {
+    decode_char_with(code, gamedef, &DecodeOptions::default())
+}
+
+/// Like `decode_char`, with the given `options`.
+pub fn decode_char_with<'a>(
     code: u16,
-    charset: &[char],
-    compound_map: &'a HashMap<char, String>,
+    gamedef: &'a GameDef,
+    options: &DecodeOptions,
 ) -> Result<Char<'a>, EncodingError> {
-    let i = (code & 0x7FFF) as usize;
-    let ch = charset
-        .get(i)
-        .cloned()
-        .ok_or_else(|| EncodingError::IllegalCharCode(code))?;
-    if (ch == '\0') { 
-        println!("Unmapped character at codepoint {}", i);
+    let ch = gamedef
+        .glyph_of(code)
+        .ok_or(EncodingError::IllegalCharCode(code))?;
+    // The charset decides which slot a Private Use Area character occupies, while the compound
+    // map decides what it stands for: a mapped PUA character decodes to its expansion.
+    match gamedef.compound_chars.get(&ch) {
+        Some(s) => Ok(Char::Compound(s)),
+        None if options.literal_unmapped_pua => Ok(Char::Regular(ch)),
+        None => match ch {
+            '\u{e000}'..='\u{f8ff}' => Err(EncodingError::PuaCharNotMapped(code, ch)),
+            _ => Ok(Char::Regular(ch)),
+        },
     }
-    if let '\u{e000}'..='\u{f8ff}' = ch {
-        // Private Use Area
-        compound_map
-            .get(&ch)
-            .map(|s| Char::Compound(s))
-            .ok_or_else(|| EncodingError::PuaCharNotMapped(code, ch))
-    } else {
-        Ok(Char::Regular(ch))
+}
+
+/// Lists what `encode_str` makes of each character of `s`, one per line: the character,
+/// the charset index of its glyph and the bytes of its character code. Compound characters
+/// are written in brackets, as in text files, and flagged as such.
+pub fn show_encoding(
+    s: &Text,
+    gamedef: &GameDef,
+    convert_to_fullwidth: bool,
+) -> Result<String, EncodingError> {
+    let mut out = String::new();
+    for ch in substitute(s, gamedef, convert_to_fullwidth) {
+        let index = char_index(&ch, gamedef)?;
+        let [hi, lo] = gamedef.codepoint_to_disk_bytes(index);
+        let line = match ch {
+            Char::Regular(c) => format!("{}\t{}\t{:02X} {:02X}", c, index, hi, lo),
+            Char::Compound(s) => {
+                format!("[{}]\t{}\t{:02X} {:02X}\tcompound", s, index, hi, lo)
+            }
+        };
+        out.push_str(&line);
+        out.push('\n');
     }
+
+    Ok(out)
+}
+
+/// Collects the distinct characters needed to write every word in `words`.
+/// Soft hyphens are left out, as they are never encoded.
+pub fn required_glyphs(words: &[&str]) -> BTreeSet<char> {
+    words
+        .iter()
+        .flat_map(|word| word.chars())
+        .filter(|ch| *ch != SOFT_HYPHEN)
+        .collect()
 }
 
 pub fn to_halfwidth<'a>(s: &'a Text, encoding_maps: &'a EncodingMaps) -> Text<'a> {
@@ -223,7 +509,7 @@ pub fn is_fullwidth_ch(ch: char) -> bool {
     ('\u{ff00}'..='\u{ff7f}').contains(&ch)
 }
 
-fn replace_halfwidth(ch: char) -> char {
+pub fn replace_halfwidth(ch: char) -> char {
     match ch {
         '\u{20}' => FULLWIDTH_SPACE,
         '\u{21}'..='\u{007f}' => std::char::from_u32(ch as u32 + 0xfee0u32).unwrap(),
@@ -239,6 +525,25 @@ pub fn replace_fullwidth(ch: char) -> char {
     }
 }
 
+impl From<EncodingError> for DecodeError {
+    fn from(err: EncodingError) -> Self {
+        DecodeError::Encoding(err)
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TruncatedInput { byte_len } => write!(
+                f,
+                "the input is {} bytes long, which is not a whole number of character codes",
+                byte_len
+            ),
+            DecodeError::Encoding(err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
 impl fmt::Display for EncodingError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -246,7 +551,11 @@ impl fmt::Display for EncodingError {
                 write!(f, "illegal character code ({:#X})", code)
             }
             EncodingError::CharNotInCharset(ch) => {
-                write!(f, "character '{}' is not present in the charset", ch.escape_unicode())
+                write!(
+                    f,
+                    "character '{}' is not present in the charset",
+                    ch.escape_unicode()
+                )
             }
             EncodingError::PuaCharNotMapped(code, ch) => write!(
                 f,
@@ -254,6 +563,14 @@ impl fmt::Display for EncodingError {
                 code,
                 ch.escape_unicode()
             ),
+            EncodingError::CharNotAllowed(ch) => {
+                write!(f, "character '{}' is not allowed", ch.escape_unicode())
+            }
+            EncodingError::RecordOverflow { encoded, capacity } => write!(
+                f,
+                "text encodes to {} characters, but the record holds {}",
+                encoded, capacity
+            ),
         }
     }
 }
@@ -284,7 +601,7 @@ mod tests {
         let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
         let ch = Char::Regular('A');
         let code = encode_char(&ch, &gamedef).unwrap();
-        let decoded = decode_char(code, gamedef.charset(), &gamedef.compound_chars);
+        let decoded = decode_char(code, gamedef);
         assert_eq!(decoded, Ok(ch));
     }
 
@@ -293,7 +610,7 @@ mod tests {
         let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
         let ch = Char::Compound("ü");
         let code = encode_char(&ch, &gamedef).unwrap();
-        let decoded = decode_char(code, gamedef.charset(), &gamedef.compound_chars);
+        let decoded = decode_char(code, gamedef);
         assert_eq!(decoded, Ok(ch));
     }
 
@@ -301,6 +618,211 @@ mod tests {
     fn decode_invalid() {
         let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
         let code = 40270u16;
-        assert!(decode_char(code, gamedef.charset(), &gamedef.compound_chars).is_err());
+        assert!(decode_char(code, gamedef).is_err());
+    }
+
+    #[test]
+    fn pua_in_charset_and_compound_map() {
+        let gamedef = gamedef::test_def(" a\u{E000}\u{E001}", "[E000]=ab");
+
+        // Both the expansion and the PUA character itself encode to the same slot...
+        let expansion = encode_str(&Text(Cow::from("[ab]")), &gamedef, false).unwrap();
+        let literal = encode_str(&Text(Cow::from("\u{E000}")), &gamedef, false).unwrap();
+        assert_eq!(expansion, vec![0x8002]);
+        assert_eq!(literal, expansion);
+        // ...which decodes to the expansion, as the compound map takes precedence.
+        let decoded = decode_str(&literal, &gamedef, false).unwrap();
+        assert_eq!(decoded.as_str(), "[ab]");
+
+        // An unmapped PUA character only decodes when asked to be taken as a plain glyph.
+        let codes = encode_str(&Text(Cow::from("a\u{E001}")), &gamedef, false).unwrap();
+        assert_eq!(codes, vec![0x8001, 0x8003]);
+        assert_eq!(
+            decode_str(&codes, &gamedef, false),
+            Err(EncodingError::PuaCharNotMapped(0x8003, '\u{E001}'))
+        );
+        let options = DecodeOptions {
+            literal_unmapped_pua: true,
+            ..DecodeOptions::default()
+        };
+        let mut decoded = String::new();
+        decode_into_with(&codes, &gamedef, false, &options, &mut decoded).unwrap();
+        assert_eq!(decoded, "a\u{E001}");
+    }
+
+    #[test]
+    fn required_glyphs_of_words() {
+        let glyphs = required_glyphs(&["Okabe", "Kurisu", "Mayu\u{ad}shii", ""]);
+        let expected: BTreeSet<_> = "OKMabehikrsuy".chars().collect();
+        assert_eq!(glyphs, expected);
+        assert!(required_glyphs(&[]).is_empty());
+    }
+
+    #[test]
+    fn expand_tabs_before_encoding() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let options = EncodeOptions {
+            tab_width: Some(DEFAULT_TAB_WIDTH),
+            ..EncodeOptions::default()
+        };
+        let line = options.normalize("\tab\tc");
+        assert_eq!(line, "    ab  c");
+
+        let codes = encode_str(&Text(line), gamedef, false).unwrap();
+        let space = encode_char(&Char::Regular(FULLWIDTH_SPACE), gamedef).unwrap();
+        let a = encode_char(&Char::Regular('a'), gamedef).unwrap();
+        let b = encode_char(&Char::Regular('b'), gamedef).unwrap();
+        let c = encode_char(&Char::Regular('c'), gamedef).unwrap();
+        assert_eq!(
+            codes,
+            vec![space, space, space, space, a, b, space, space, c]
+        );
+
+        assert_eq!(EncodeOptions::default().normalize("\tab"), "\tab");
+    }
+
+    #[test]
+    fn recompose_before_encoding() {
+        let gamedef = gamedef::test_def(" Vitệ", "");
+        let line = "Vie\u{323}\u{302}t";
+        assert!(encode_str(&Text(Cow::from(line)), &gamedef, false).is_err());
+
+        let options = EncodeOptions {
+            recompose: true,
+            ..EncodeOptions::default()
+        };
+        let codes = encode_str(&Text(options.normalize(line)), &gamedef, false).unwrap();
+        assert_eq!(codes, vec![0x8001, 0x8002, 0x8004, 0x8003]);
+    }
+
+    #[test]
+    fn trim_policy() {
+        let line = "\u{3000}Okabe  ";
+        let trim = |trim| EncodeOptions {
+            trim,
+            ..EncodeOptions::default()
+        };
+        assert_eq!(trim(TrimPolicy::None).normalize(line), line);
+        assert_eq!(trim(TrimPolicy::Trailing).normalize(line), "\u{3000}Okabe");
+        assert_eq!(trim(TrimPolicy::Both).normalize(line), "Okabe");
+
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let kept = encode_str(
+            &Text(trim(TrimPolicy::None).normalize(line)),
+            gamedef,
+            false,
+        );
+        let trimmed = encode_str(
+            &Text(trim(TrimPolicy::Trailing).normalize(line)),
+            gamedef,
+            false,
+        );
+        assert_eq!(kept.unwrap().len(), 8);
+        assert_eq!(trimmed.unwrap().len(), 6);
+    }
+
+    #[test]
+    fn show_encoded_bytes() {
+        let gamedef = gamedef::test_def(" Tu\u{E000}", "[E000]=¹⁸");
+        let s = Text(Cow::from("Tu[¹⁸]"));
+        assert_eq!(
+            show_encoding(&s, &gamedef, false).unwrap(),
+            "T\t1\t80 01\nu\t2\t80 02\n[¹⁸]\t3\t80 03\tcompound\n"
+        );
+        assert!(show_encoding(&Text(Cow::from("Tutu!")), &gamedef, false).is_err());
+    }
+
+    #[test]
+    fn decode_padding_placeholder() {
+        let gamedef = gamedef::test_def(" ab c", "");
+        // 'a', padding, 'c'
+        let codes = [0x8001, 0x8003, 0x8004];
+        let options = DecodeOptions {
+            padding_placeholder: Some(DEFAULT_PADDING_PLACEHOLDER),
+            ..DecodeOptions::default()
+        };
+        let mut out = String::new();
+        decode_into_with(&codes, &gamedef, false, &options, &mut out).unwrap();
+        assert_eq!(out, "a·c");
+
+        let past_end = 0x8000 + gamedef.charset().len() as u16;
+        assert_eq!(
+            decode_into_with(&[past_end], &gamedef, false, &options, &mut out),
+            Err(EncodingError::IllegalCharCode(past_end))
+        );
+    }
+
+    #[test]
+    fn decode_truncated_bytes() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let codes = encode_str(&Text(Cow::from("Okabe")), gamedef, false).unwrap();
+        let bytes: Vec<_> = codes.iter().flat_map(|code| code.to_be_bytes()).collect();
+        assert_eq!(
+            decode_bytes(&bytes, gamedef, false).unwrap().as_str(),
+            "Okabe"
+        );
+
+        assert_eq!(
+            decode_bytes(&bytes[..5], gamedef, false),
+            Err(DecodeError::TruncatedInput { byte_len: 5 })
+        );
+    }
+
+    #[test]
+    fn decode_into_appends() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let codes = encode_str(&Text(Cow::from("Okabe[ü]")), gamedef, false).unwrap();
+
+        let mut buf = "Hououin ".to_string();
+        decode_into(&codes, gamedef, false, &mut buf).unwrap();
+        assert_eq!(buf, "Hououin Okabe[ü]");
+
+        let decoded = decode_str(&codes, gamedef, false).unwrap();
+        assert_eq!(decoded.as_str(), &buf["Hououin ".len()..]);
+
+        let invalid = 0x8000 + gamedef.charset().len() as u16;
+        assert!(decode_into(&[codes[0], invalid], gamedef, false, &mut buf).is_err());
+        assert_eq!(buf, "Hououin Okabe[ü]");
+    }
+
+    #[test]
+    fn encoded_byte_len_matches_encode() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        for s in &["", "A", "Okabe Rintarou", "ruby[ü]", "Ｏｋａｂｅ"] {
+            let text = Text(Cow::from(*s));
+            for fullwidth in &[false, true] {
+                let codes = encode_str(&text, gamedef, *fullwidth).unwrap();
+                let len = encoded_byte_len(&text, gamedef, *fullwidth).unwrap();
+                assert_eq!(len, codes.len() * 2, "{}", s);
+            }
+        }
+        assert!(encoded_byte_len(&Text(Cow::from("\u{1F600}")), gamedef, false).is_err());
+    }
+
+    #[test]
+    fn forward_table_roundtrip() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let table = gamedef.encoding_maps.forward_table();
+        assert!(!table.is_empty());
+        assert!(table.windows(2).all(|w| w[0].0 < w[1].0));
+        for (ch, index) in table {
+            assert_eq!(gamedef.encoding_maps.index_of(ch), Some(index));
+            assert_eq!(gamedef.charset()[index as usize], ch);
+        }
+    }
+
+    #[test]
+    fn encode_strips_soft_hyphens() {
+        let gamedef = gamedef::get(gamedef::Game::SteinsGate0);
+        let hyphenated = Text(Cow::from("Oka\u{ad}rin"));
+        let codes = encode_str(&hyphenated, gamedef, false).unwrap();
+        assert_eq!(
+            codes,
+            encode_str(&Text(Cow::from("Okarin")), gamedef, false).unwrap()
+        );
+        assert_eq!(
+            encoded_byte_len(&hyphenated, gamedef, false).unwrap(),
+            codes.len() * 2
+        );
     }
 }
@@ -0,0 +1,54 @@
+use std::{fmt, slice};
+
+/// A non-fatal problem noticed while processing, left for the caller to present.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Warning {
+    /// A character the target game cannot represent was replaced with a substitute.
+    Substituted(String),
+    /// A character in a game's fullwidth blocklist has no fullwidth form, so listing it has no effect.
+    NotFullwidthConvertible(char),
+    /// The expansion of a compound character holds a Private Use Area character
+    /// (compound character, PUA character).
+    PuaInCompoundExpansion(char, char),
+}
+
+/// Collects the warnings raised by library functions, in the order they were raised.
+#[derive(Debug, Default)]
+pub struct Warnings(Vec<Warning>);
+
+impl Warnings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, warning: Warning) {
+        self.0.push(warning);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Warning> {
+        self.0.iter()
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::Substituted(ch) => write!(f, "substituted '{}'", ch),
+            Warning::NotFullwidthConvertible(ch) => write!(
+                f,
+                "'{}' is in the fullwidth blocklist, but has no fullwidth form",
+                ch.escape_unicode()
+            ),
+            Warning::PuaInCompoundExpansion(ch, pua) => write!(
+                f,
+                "the expansion of compound character {} holds the Private Use Area character {}",
+                ch.escape_unicode(),
+                pua.escape_unicode()
+            ),
+        }
+    }
+}